@@ -0,0 +1,113 @@
+use crate::{ray::Ray, vec3::Vec3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    min: Vec3,
+    max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn min(&self) -> &Vec3 {
+        &self.min
+    }
+
+    pub fn max(&self) -> &Vec3 {
+        &self.max
+    }
+
+    // Grows every side by `delta`.
+    pub fn padded(&self, delta: f32) -> Self {
+        let d = Vec3::new(delta, delta, delta);
+        Aabb {
+            min: self.min - d,
+            max: self.max + d,
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x() >= d.y() && d.x() >= d.z() {
+            0
+        } else if d.y() >= d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Self {
+        let mut bbox = *a;
+        for i in 0..3 {
+            bbox.min[i] = a.min[i].min(b.min[i]);
+            bbox.max[i] = a.max[i].max(b.max[i]);
+        }
+        bbox
+    }
+
+    pub fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.range(r, t_min, t_max).is_some()
+    }
+
+    // The part of (t_min, t_max) over which the ray is inside the box.
+    pub fn range(&self, r: &Ray, mut t_min: f32, mut t_max: f32) -> Option<(f32, f32)> {
+        for i in 0..3 {
+            let inv_d = 1.0 / r.direction()[i];
+            let mut t0 = (self.min[i] - r.origin()[i]) * inv_d;
+            let mut t1 = (self.max[i] - r.origin()[i]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            t_min = t0.max(t_min);
+            t_max = t1.min(t_max);
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Aabb;
+    use crate::{ray::Ray, vec3::Vec3};
+
+    #[test]
+    fn test_slab_hit() {
+        let bbox = Aabb::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(1.0, 1.0, 1.0));
+        let r = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(bbox.hit(&r, 0.0, f32::MAX));
+        assert_eq!(Some((4.0, 6.0)), bbox.range(&r, -f32::MAX, f32::MAX));
+        assert_eq!(Some((4.0, 5.0)), bbox.range(&r, 0.0, 5.0));
+        assert!(!bbox.hit(&r, 0.0, 3.0));
+        assert!(!bbox.hit(&r, 7.0, f32::MAX));
+        let r = Ray::new(Vec3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!bbox.hit(&r, 0.0, f32::MAX));
+        let r = Ray::new(Vec3::new(-3.0, -3.0, -3.0), Vec3::new(1.0, 1.0, 1.0));
+        assert!(bbox.hit(&r, 0.0, f32::MAX));
+    }
+
+    #[test]
+    fn test_surrounding() {
+        let a = Aabb::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0));
+        let b = Aabb::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::new(0.5, 2.5, 0.5));
+        let c = Aabb::surrounding(&a, &b);
+        assert_eq!(&Vec3::new(-1.0, 0.0, 0.0), c.min());
+        assert_eq!(&Vec3::new(1.0, 2.5, 1.0), c.max());
+        assert_eq!(1, c.longest_axis());
+        assert_eq!(2.0 * (5.0 + 2.5 + 2.0), c.surface_area());
+    }
+}
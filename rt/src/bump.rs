@@ -0,0 +1,202 @@
+use crate::{
+    aabb::Aabb,
+    hitable::{HitRecord, Hitable, Interval},
+    ray::Ray,
+    texture::Texture,
+    vec3::Vec3,
+};
+
+// The step in u and v for finite differences of heights when the ray has no
+// differentials to size it by.
+const DEFAULT_STEP: f32 = 5e-4;
+
+pub enum Perturbation {
+    // Heights from the luminance of a texture, `scale` units at white,
+    // raising the surface along its normal.
+    Bump {
+        heights: Box<dyn Texture>,
+        scale: f32,
+    },
+    // Normals in tangent space encoded as colors, with x along the tangent,
+    // y along the bitangent and z, blue, away from the surface.
+    NormalMap(Box<dyn Texture>),
+}
+
+// Gives an object's surface detail that it does not have by bending its
+// shading normals. The object has to provide dpdu and dpdv.
+pub struct Bumped<H> {
+    object: H,
+    perturbation: Perturbation,
+}
+
+impl<H: Hitable> Bumped<H> {
+    pub fn new(object: H, perturbation: Perturbation) -> Self {
+        Bumped {
+            object,
+            perturbation,
+        }
+    }
+
+    pub fn bump(object: H, heights: Box<dyn Texture>, scale: f32) -> Self {
+        Bumped::new(object, Perturbation::Bump { heights, scale })
+    }
+
+    pub fn normal_map(object: H, normals: Box<dyn Texture>) -> Self {
+        Bumped::new(object, Perturbation::NormalMap(normals))
+    }
+
+    pub fn perturbation(&self) -> &Perturbation {
+        &self.perturbation
+    }
+
+    fn perturb<'a>(&self, r: &Ray, hit: HitRecord<'a>) -> HitRecord<'a> {
+        let n = *hit.shading_normal();
+        let (u, v, p) = (hit.u(), hit.v(), *hit.p());
+        let bent = match &self.perturbation {
+            Perturbation::Bump { heights, scale } => {
+                let (du, dv) = match hit.footprint(r) {
+                    Some(f) => (
+                        0.5 * (f.dudx.abs() + f.dudy.abs()),
+                        0.5 * (f.dvdx.abs() + f.dvdy.abs()),
+                    ),
+                    None => (0.0, 0.0),
+                };
+                let du = if du > 0.0 { du } else { DEFAULT_STEP };
+                let dv = if dv > 0.0 { dv } else { DEFAULT_STEP };
+                let height = |u, v, p: Vec3| scale * heights.value(u, v, &p).luminance();
+                let h = height(u, v, p);
+                let dhdu = (height(u + du, v, p + du * *hit.dpdu()) - h) / du;
+                let dhdv = (height(u, v + dv, p + dv * *hit.dpdv()) - h) / dv;
+                Vec3::cross(&(*hit.dpdu() + dhdu * n), &(*hit.dpdv() + dhdv * n))
+            }
+            Perturbation::NormalMap(normals) => {
+                let c = 2.0 * normals.value(u, v, &p) - Vec3::new(1.0, 1.0, 1.0);
+                let (tangent, bitangent) = hit.tangents();
+                c.x() * tangent + c.y() * bitangent + c.z() * n
+            }
+        };
+        if bent.squared_length() < 1e-12 {
+            return hit;
+        }
+        let bent = bent.unit_vector();
+        // dpdu and dpdv need not follow the winding of the surface.
+        let bent = if Vec3::dot(&bent, &n) < 0.0 {
+            -bent
+        } else {
+            bent
+        };
+        hit.with_shading_normal(bent)
+    }
+}
+
+impl<H: Hitable> Hitable for Bumped<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let hit = self.object.hit(r, t_min, t_max)?;
+        Some(self.perturb(r, hit))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object.bounding_box()
+    }
+
+    fn intervals(&self, r: &Ray, t_min: f32, t_max: f32) -> Vec<Interval<'_>> {
+        self.object
+            .intervals(r, t_min, t_max)
+            .into_iter()
+            .map(|interval| Interval {
+                enter: self.perturb(r, interval.enter),
+                exit: self.perturb(r, interval.exit),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bumped;
+    use crate::{
+        hitable::Hitable,
+        material::{Lambertian, Material},
+        ray::Ray,
+        rect::XyRect,
+        sampler::Sampler,
+        test_support::{assert_near, grey},
+        texture::{SolidColor, Texture},
+        vec3::Vec3,
+    };
+
+    // Rises with u.
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, u: f32, _: f32, _: &Vec3) -> Vec3 {
+            Vec3::new(u, u, u)
+        }
+    }
+
+    fn square() -> XyRect {
+        XyRect::new((-1.0, 1.0), (-1.0, 1.0), 0.0, grey())
+    }
+
+    fn down() -> Ray {
+        Ray::new(Vec3::new(0.2, 0.3, 1.0), Vec3::new(0.0, 0.0, -1.0))
+    }
+
+    #[test]
+    fn test_bump() {
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        let flat = Bumped::bump(square(), Box::new(SolidColor::new(Vec3::zero())), 1.0);
+        let hit = flat.hit(&down(), 0.001, f32::MAX).unwrap();
+        assert_near(&up, hit.shading_normal(), 1e-3);
+
+        // Rising by 0.5 over the 2 units of the square.
+        let sloped = Bumped::bump(square(), Box::new(Ramp), 0.5);
+        let hit = sloped.hit(&down(), 0.001, f32::MAX).unwrap();
+        assert_near(
+            &Vec3::new(-0.25, 0.0, 1.0).unit_vector(),
+            hit.shading_normal(),
+            1e-3,
+        );
+        assert_eq!(&up, hit.normal());
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let mapped = |color| Bumped::normal_map(square(), Box::new(SolidColor::new(color)));
+        let hit_normal = |color| {
+            *mapped(color)
+                .hit(&down(), 0.001, f32::MAX)
+                .unwrap()
+                .shading_normal()
+        };
+        let up = Vec3::new(0.0, 0.0, 1.0);
+        assert_near(&up, &hit_normal(Vec3::new(0.5, 0.5, 1.0)), 1e-3);
+        // Leaning towards u and towards v.
+        assert_near(
+            &Vec3::new(1.0, 0.0, 1.0).unit_vector(),
+            &hit_normal(Vec3::new(1.0, 0.5, 1.0)),
+            1e-3,
+        );
+        assert_near(
+            &Vec3::new(0.0, 1.0, 1.0).unit_vector(),
+            &hit_normal(Vec3::new(0.5, 1.0, 1.0)),
+            1e-3,
+        );
+
+        // Diffuse light off a steeply bent normal stays above the surface.
+        let steep = mapped(Vec3::new(1.0, 0.5, 0.55));
+        let hit = steep.hit(&down(), 0.001, f32::MAX).unwrap();
+        assert_eq!(&up, hit.normal());
+        let mut sampler = Sampler::new(1);
+        let material = Lambertian::new(Vec3::new(0.5, 0.5, 0.5));
+        for _ in 0..100 {
+            if let Some((_, scattered)) = material.scatter(&down(), &hit, &mut sampler) {
+                assert!(Vec3::dot(scattered.direction(), &up) > 0.0);
+            }
+        }
+        let (f, _) = material
+            .eval(&down(), &hit, &Vec3::new(1.0, 0.0, -0.1))
+            .unwrap();
+        assert_eq!(Vec3::zero(), f);
+    }
+}
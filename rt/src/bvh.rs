@@ -0,0 +1,504 @@
+use crate::{
+    aabb::Aabb,
+    hitable::{HitRecord, Hitable, HitableList},
+    ray::Ray,
+    vec3::Vec3,
+};
+
+pub struct BvhNode {
+    bbox: Aabb,
+    left: Box<dyn Hitable>,
+    right: Option<Box<dyn Hitable>>,
+}
+
+impl BvhNode {
+    // Panics if the list is empty or holds an object without a bounding box.
+    pub fn new(list: HitableList) -> Self {
+        let objs = list
+            .into_vec()
+            .into_iter()
+            .map(|obj| {
+                let bbox = obj.bounding_box().expect("no bounding box in BvhNode::new");
+                (bbox, obj)
+            })
+            .collect();
+        Self::build(objs)
+    }
+
+    fn build(mut objs: Vec<(Aabb, Box<dyn Hitable>)>) -> Self {
+        assert!(!objs.is_empty(), "BvhNode::new called with an empty list");
+        if objs.len() == 1 {
+            let (bbox, obj) = objs.pop().unwrap();
+            return BvhNode {
+                bbox,
+                left: obj,
+                right: None,
+            };
+        }
+        let (first, rest) = objs.split_first().unwrap();
+        let centroid = first.0.centroid();
+        let centroids = rest
+            .iter()
+            .fold(Aabb::new(centroid, centroid), |bbox, (b, _)| {
+                let c = b.centroid();
+                Aabb::surrounding(&bbox, &Aabb::new(c, c))
+            });
+        let axis = centroids.longest_axis();
+        objs.sort_by(|(a, _), (b, _)| a.centroid()[axis].total_cmp(&b.centroid()[axis]));
+        let right = objs.split_off(objs.len() / 2);
+        let left = Self::build_child(objs);
+        let right = Self::build_child(right);
+        BvhNode {
+            bbox: Aabb::surrounding(&left.0, &right.0),
+            left: left.1,
+            right: Some(right.1),
+        }
+    }
+
+    fn build_child(mut objs: Vec<(Aabb, Box<dyn Hitable>)>) -> (Aabb, Box<dyn Hitable>) {
+        if objs.len() == 1 {
+            objs.pop().unwrap()
+        } else {
+            let node = Self::build(objs);
+            (node.bbox, Box::new(node))
+        }
+    }
+}
+
+impl Hitable for BvhNode {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        if !self.bbox.hit(r, t_min, t_max) {
+            return None;
+        }
+        let left = self.left.hit(r, t_min, t_max);
+        let closest = left.as_ref().map_or(t_max, |hit| hit.t());
+        let right = self
+            .right
+            .as_ref()
+            .and_then(|right| right.hit(r, t_min, closest));
+        right.or(left)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(self.bbox)
+    }
+}
+
+// Costs used by the surface area heuristic, relative to one primitive test.
+const TRAVERSAL_COST: f32 = 0.125;
+const INTERSECT_COST: f32 = 1.0;
+const SAH_BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+// Past this depth nodes are split at the median, which bounds the traversal
+// stack even for inputs the heuristic handles badly.
+const MAX_SAH_DEPTH: usize = 32;
+
+#[derive(Clone, Copy, Debug)]
+struct FlatNode {
+    bbox: Aabb,
+    // First primitive of a leaf, or the second child of an interior node whose
+    // first child immediately follows it.
+    offset: u32,
+    count: u16,
+    axis: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BvhStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub depth: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    pub mean_leaf_size: f32,
+    // Expected cost of a random ray in units of primitive tests; a plain
+    // `HitableList` of n objects costs n.
+    pub sah_cost: f32,
+}
+
+// A binned-SAH tree over primitive indices, stored as one depth-first array.
+// It only deals with bounding boxes, so it can index any primitive storage.
+pub struct BvhTree {
+    nodes: Vec<FlatNode>,
+    indices: Vec<usize>,
+}
+
+impl BvhTree {
+    pub fn new(bounds: &[Aabb]) -> Self {
+        let mut tree = BvhTree {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: (0..bounds.len()).collect(),
+        };
+        if !bounds.is_empty() {
+            let centroids: Vec<Vec3> = bounds.iter().map(|b| b.centroid()).collect();
+            let mut indices = std::mem::take(&mut tree.indices);
+            tree.build(bounds, &centroids, &mut indices, 0, 0);
+            tree.indices = indices;
+        }
+        tree
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.bbox)
+    }
+
+    fn build(
+        &mut self,
+        bounds: &[Aabb],
+        centroids: &[Vec3],
+        indices: &mut [usize],
+        first: usize,
+        depth: usize,
+    ) -> usize {
+        let bbox = enclose(indices.iter().map(|&i| bounds[i]));
+        let node = self.nodes.len();
+        self.nodes.push(FlatNode {
+            bbox,
+            offset: first as u32,
+            count: indices.len() as u16,
+            axis: 0,
+        });
+        let centroid_bounds = enclose(
+            indices
+                .iter()
+                .map(|&i| Aabb::new(centroids[i], centroids[i])),
+        );
+        let leaf_cost = INTERSECT_COST * indices.len() as f32;
+        let split = if depth < MAX_SAH_DEPTH {
+            find_split(bounds, centroids, indices, &centroid_bounds)
+        } else {
+            None
+        };
+        let mid = match split {
+            Some((axis, bin, cost)) if cost < leaf_cost || indices.len() > MAX_LEAF_SIZE => {
+                self.nodes[node].axis = axis as u8;
+                let (lo, extent) = (
+                    centroid_bounds.min()[axis],
+                    centroid_bounds.max()[axis] - centroid_bounds.min()[axis],
+                );
+                partition(indices, |&i| bin_of(centroids[i][axis], lo, extent) <= bin)
+            }
+            None if indices.len() > MAX_LEAF_SIZE => {
+                let axis = centroid_bounds.longest_axis();
+                self.nodes[node].axis = axis as u8;
+                indices.sort_by(|&a, &b| centroids[a][axis].total_cmp(&centroids[b][axis]));
+                indices.len() / 2
+            }
+            _ => return node,
+        };
+        let (left, right) = indices.split_at_mut(mid);
+        self.build(bounds, centroids, left, first, depth + 1);
+        let second = self.build(bounds, centroids, right, first + mid, depth + 1);
+        self.nodes[node].offset = second as u32;
+        self.nodes[node].count = 0;
+        node
+    }
+
+    // `hit_primitive` is called with a primitive index and the current closest
+    // distance, and returns a hit closer than that distance if there is one.
+    pub fn hit<'a, F>(
+        &self,
+        r: &Ray,
+        t_min: f32,
+        t_max: f32,
+        mut hit_primitive: F,
+    ) -> Option<HitRecord<'a>>
+    where
+        F: FnMut(usize, f32) -> Option<HitRecord<'a>>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let negative = [
+            r.direction().x() < 0.0,
+            r.direction().y() < 0.0,
+            r.direction().z() < 0.0,
+        ];
+        let mut closest = t_max;
+        let mut hit = None;
+        let mut stack = [0usize; 64];
+        let mut top = 1;
+        while top > 0 {
+            top -= 1;
+            let index = stack[top];
+            let node = &self.nodes[index];
+            if !node.bbox.hit(r, t_min, closest) {
+                continue;
+            }
+            if node.count > 0 {
+                let first = node.offset as usize;
+                for &i in &self.indices[first..first + node.count as usize] {
+                    if let Some(this_hit) = hit_primitive(i, closest) {
+                        closest = this_hit.t();
+                        hit = Some(this_hit);
+                    }
+                }
+            } else if negative[node.axis as usize] {
+                stack[top] = index + 1;
+                stack[top + 1] = node.offset as usize;
+                top += 2;
+            } else {
+                stack[top] = node.offset as usize;
+                stack[top + 1] = index + 1;
+                top += 2;
+            }
+        }
+        hit
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats {
+            nodes: self.nodes.len(),
+            leaves: 0,
+            depth: 0,
+            min_leaf_size: 0,
+            max_leaf_size: 0,
+            mean_leaf_size: 0.0,
+            sah_cost: 0.0,
+        };
+        if self.nodes.is_empty() {
+            return stats;
+        }
+        let root_area = self.nodes[0].bbox.surface_area().max(f32::MIN_POSITIVE);
+        stats.min_leaf_size = usize::MAX;
+        let mut stack = vec![(0, 1)];
+        while let Some((index, depth)) = stack.pop() {
+            let node = &self.nodes[index];
+            let area = node.bbox.surface_area() / root_area;
+            stats.depth = stats.depth.max(depth);
+            if node.count > 0 {
+                let count = node.count as usize;
+                stats.leaves += 1;
+                stats.min_leaf_size = stats.min_leaf_size.min(count);
+                stats.max_leaf_size = stats.max_leaf_size.max(count);
+                stats.sah_cost += area * INTERSECT_COST * count as f32;
+            } else {
+                stats.sah_cost += area * TRAVERSAL_COST;
+                stack.push((index + 1, depth + 1));
+                stack.push((node.offset as usize, depth + 1));
+            }
+        }
+        stats.mean_leaf_size = self.indices.len() as f32 / stats.leaves as f32;
+        stats
+    }
+}
+
+fn enclose<I: Iterator<Item = Aabb>>(mut boxes: I) -> Aabb {
+    let first = boxes.next().unwrap();
+    boxes.fold(first, |acc, b| Aabb::surrounding(&acc, &b))
+}
+
+fn bin_of(c: f32, lo: f32, extent: f32) -> usize {
+    (((c - lo) / extent * SAH_BINS as f32) as usize).min(SAH_BINS - 1)
+}
+
+// Returns the axis, the last bin of the left side and the cost of the cheapest
+// split, or None when the centroids cannot be told apart on any axis.
+fn find_split(
+    bounds: &[Aabb],
+    centroids: &[Vec3],
+    indices: &[usize],
+    centroid_bounds: &Aabb,
+) -> Option<(usize, usize, f32)> {
+    let parent_area = enclose(indices.iter().map(|&i| bounds[i]))
+        .surface_area()
+        .max(f32::MIN_POSITIVE);
+    let mut best: Option<(usize, usize, f32)> = None;
+    for axis in 0..3 {
+        let lo = centroid_bounds.min()[axis];
+        let extent = centroid_bounds.max()[axis] - lo;
+        if extent <= 0.0 {
+            continue;
+        }
+        let mut bins: [(Option<Aabb>, usize); SAH_BINS] = [(None, 0); SAH_BINS];
+        for &i in indices {
+            let bin = &mut bins[bin_of(centroids[i][axis], lo, extent)];
+            bin.0 = Some(
+                bin.0
+                    .map_or(bounds[i], |b| Aabb::surrounding(&b, &bounds[i])),
+            );
+            bin.1 += 1;
+        }
+        // Sweep from the right to get the area and count of every right side.
+        let mut right = [(0.0, 0); SAH_BINS];
+        let (mut bbox, mut count): (Option<Aabb>, usize) = (None, 0);
+        for b in (1..SAH_BINS).rev() {
+            bbox = merge(bbox, bins[b].0);
+            count += bins[b].1;
+            right[b] = (bbox.map_or(0.0, |b| b.surface_area()), count);
+        }
+        let (mut bbox, mut count): (Option<Aabb>, usize) = (None, 0);
+        for b in 0..SAH_BINS - 1 {
+            bbox = merge(bbox, bins[b].0);
+            count += bins[b].1;
+            let (right_area, right_count) = right[b + 1];
+            if count == 0 || right_count == 0 {
+                continue;
+            }
+            let left_area = bbox.map_or(0.0, |b| b.surface_area());
+            let cost = TRAVERSAL_COST
+                + INTERSECT_COST * (left_area * count as f32 + right_area * right_count as f32)
+                    / parent_area;
+            if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, b, cost));
+            }
+        }
+    }
+    best
+}
+
+fn merge(a: Option<Aabb>, b: Option<Aabb>) -> Option<Aabb> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(Aabb::surrounding(&a, &b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+// Moves the elements matching `pred` to the front and returns how many there are.
+fn partition<T, F: Fn(&T) -> bool>(items: &mut [T], pred: F) -> usize {
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+pub struct Bvh {
+    tree: BvhTree,
+    objs: Vec<Box<dyn Hitable>>,
+}
+
+impl Bvh {
+    // Panics if the list holds an object without a bounding box.
+    pub fn new(list: HitableList) -> Self {
+        let objs = list.into_vec();
+        let bounds: Vec<Aabb> = objs
+            .iter()
+            .map(|obj| obj.bounding_box().expect("no bounding box in Bvh::new"))
+            .collect();
+        Bvh {
+            tree: BvhTree::new(&bounds),
+            objs,
+        }
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        self.tree.stats()
+    }
+}
+
+impl Hitable for Bvh {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.tree.hit(r, t_min, t_max, |i, closest| {
+            self.objs[i].hit(r, t_min, closest)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.tree.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bvh, BvhNode};
+    use crate::{
+        hitable::{Hitable, HitableList, Sphere},
+        ray::Ray,
+        sampler::Sampler,
+        test_support::grey,
+        vec3::Vec3,
+    };
+    use rand::Rng;
+
+    fn random_spheres(rng: &mut Sampler, n: usize) -> HitableList {
+        let mut list = HitableList::new();
+        for _ in 0..n {
+            let center =
+                Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 20.0 - Vec3::new(10.0, 10.0, 10.0);
+            list.push(Box::new(Sphere::new(
+                center,
+                0.1 + rng.gen::<f32>(),
+                grey(),
+            )));
+        }
+        list
+    }
+
+    #[test]
+    fn test_bvh_matches_list() {
+        let mut rng = Sampler::new(1);
+        let list = random_spheres(&mut rng, 200);
+        let bvh = BvhNode::new(random_spheres(&mut Sampler::new(1), 200));
+        assert_eq!(list.bounding_box(), bvh.bounding_box());
+        for _ in 0..1000 {
+            let origin =
+                Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 30.0 - Vec3::new(15.0, 15.0, 15.0);
+            let direction = Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(0.5, 0.5, 0.5);
+            let r = Ray::new(origin, direction);
+            let expected = list.hit(&r, 0.001, f32::MAX).map(|hit| hit.t());
+            let actual = bvh.hit(&r, 0.001, f32::MAX).map(|hit| hit.t());
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn test_nan_bounds() {
+        // A degenerate object must neither panic the build nor hide the rest.
+        let with_nan = || {
+            let mut list = random_spheres(&mut Sampler::new(4), 200);
+            let center = Vec3::new(f32::NAN, 0.0, 0.0);
+            list.push(Box::new(Sphere::new(center, 1.0, grey())));
+            list
+        };
+        let list = random_spheres(&mut Sampler::new(4), 200);
+        let node = BvhNode::new(with_nan());
+        let bvh = Bvh::new(with_nan());
+        let mut rng = Sampler::new(5);
+        for _ in 0..1000 {
+            let origin =
+                Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 30.0 - Vec3::new(15.0, 15.0, 15.0);
+            let direction = Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(0.5, 0.5, 0.5);
+            let r = Ray::new(origin, direction);
+            let expected = list.hit(&r, 0.001, f32::MAX).map(|hit| hit.t());
+            assert_eq!(expected, node.hit(&r, 0.001, f32::MAX).map(|hit| hit.t()));
+            assert_eq!(expected, bvh.hit(&r, 0.001, f32::MAX).map(|hit| hit.t()));
+        }
+    }
+
+    #[test]
+    fn test_sah_bvh_matches_list() {
+        let mut rng = Sampler::new(2);
+        let list = random_spheres(&mut rng, 500);
+        let bvh = Bvh::new(random_spheres(&mut Sampler::new(2), 500));
+        assert_eq!(list.bounding_box(), bvh.bounding_box());
+        for _ in 0..1000 {
+            let origin =
+                Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 30.0 - Vec3::new(15.0, 15.0, 15.0);
+            let direction = Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(0.5, 0.5, 0.5);
+            let r = Ray::new(origin, direction);
+            let expected = list.hit(&r, 0.001, f32::MAX).map(|hit| hit.t());
+            let actual = bvh.hit(&r, 0.001, f32::MAX).map(|hit| hit.t());
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn test_sah_bvh_stats() {
+        let bvh = Bvh::new(random_spheres(&mut Sampler::new(3), 500));
+        let stats = bvh.stats();
+        assert_eq!(stats.nodes, 2 * stats.leaves - 1);
+        assert_eq!(500.0, stats.mean_leaf_size * stats.leaves as f32);
+        assert!(stats.min_leaf_size >= 1 && stats.max_leaf_size <= 4);
+        assert!(stats.depth < 64);
+        assert!(stats.sah_cost < 500.0 / 4.0);
+
+        let empty = Bvh::new(HitableList::new());
+        assert_eq!(0, empty.stats().nodes);
+        assert!(empty.bounding_box().is_none());
+    }
+}
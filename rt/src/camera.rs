@@ -1,7 +1,12 @@
-use crate::{ray::Ray, vec3::Vec3};
+use crate::{
+    ray::{Differentials, Ray},
+    sampler::Sampler,
+    vec3::Vec3,
+};
 use std::f32::consts::PI;
-use rand::{thread_rng, Rng};
+use rand::Rng;
 
+#[derive(Clone, Copy, Debug)]
 pub struct Camera {
     origin: Vec3,
     lower_left_corner: Vec3,
@@ -11,6 +16,9 @@ pub struct Camera {
     v: Vec3,
     _w: Vec3,
     lens_radius: f32,
+    time0: f32,
+    time1: f32,
+    pixel_size: Option<(f32, f32)>,
 }
 
 impl Camera {
@@ -32,23 +40,54 @@ impl Camera {
             vertical,
             u, v, _w: w,
             lens_radius: aperture / 2.0,
+            time0: 0.0,
+            time1: 0.0,
+            pixel_size: None,
         }
     }
 
-    pub fn get_ray(&self, u: f32, v: f32) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
+    // Rays are sent at uniformly random times while the shutter is open.
+    pub fn with_shutter(mut self, open: f32, close: f32) -> Self {
+        self.time0 = open;
+        self.time1 = close;
+        self
+    }
+
+    // Once the camera knows the size of the image its rays carry
+    // differentials for a step of one pixel.
+    pub fn with_resolution(mut self, width: usize, height: usize) -> Self {
+        self.pixel_size = Some((1.0 / width as f32, 1.0 / height as f32));
+        self
+    }
+
+    pub fn get_ray(&self, u: f32, v: f32, sampler: &mut Sampler) -> Ray {
+        let rd = self.lens_radius * random_in_unit_disk(sampler);
         let offset = self.u * rd.x() + self.v * rd.y();
-        Ray::new(
-            self.origin + offset,
-            self.lower_left_corner + u * self.horizontal + v * self.vertical - self.origin - offset,
-        )
+        let time = if self.time1 > self.time0 {
+            sampler.gen_range(self.time0, self.time1)
+        } else {
+            self.time0
+        };
+        let origin = self.origin + offset;
+        let direction = |u: f32, v: f32| {
+            self.lower_left_corner + u * self.horizontal + v * self.vertical - origin
+        };
+        let r = Ray::new(origin, direction(u, v)).with_time(time);
+        match self.pixel_size {
+            Some((du, dv)) => r.with_differentials(Differentials {
+                rx_origin: origin,
+                rx_direction: direction(u + du, v),
+                ry_origin: origin,
+                ry_direction: direction(u, v + dv),
+            }),
+            None => r,
+        }
     }
 }
 
-fn random_in_unit_disk() -> Vec3 {
-    let mut rng = thread_rng();
+fn random_in_unit_disk(sampler: &mut Sampler) -> Vec3 {
     loop {
-        let p = 2.0 * Vec3::new(rng.gen(), rng.gen(), 0.0) - Vec3::new(1.0, 1.0, 0.0);
+        let p = 2.0 * Vec3::new(sampler.gen(), sampler.gen(), 0.0) - Vec3::new(1.0, 1.0, 0.0);
         if p.squared_length() < 1.0 {
             break p;
         }
@@ -0,0 +1,217 @@
+use crate::{
+    aabb::Aabb,
+    hitable::{HitRecord, Hitable, Interval},
+    ray::Ray,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    // The first object with the second cut out of it.
+    Difference,
+}
+
+impl Operation {
+    fn inside(self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            Operation::Union => in_a || in_b,
+            Operation::Intersection => in_a && in_b,
+            Operation::Difference => in_a && !in_b,
+        }
+    }
+}
+
+// Combines two closed objects with outward normals. Each surface keeps its
+// own material; where the second object is cut out of the first its normals
+// are turned around.
+pub struct Csg<A, B> {
+    a: A,
+    b: B,
+    operation: Operation,
+}
+
+impl<A: Hitable, B: Hitable> Csg<A, B> {
+    pub fn new(a: A, b: B, operation: Operation) -> Self {
+        Csg { a, b, operation }
+    }
+
+    pub fn union(a: A, b: B) -> Self {
+        Csg::new(a, b, Operation::Union)
+    }
+
+    pub fn intersection(a: A, b: B) -> Self {
+        Csg::new(a, b, Operation::Intersection)
+    }
+
+    pub fn difference(a: A, b: B) -> Self {
+        Csg::new(a, b, Operation::Difference)
+    }
+
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+}
+
+impl<A: Hitable, B: Hitable> Hitable for Csg<A, B> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        if let Some(bbox) = self.bounding_box() {
+            if !bbox.hit(r, t_min, t_max) {
+                return None;
+            }
+        }
+        self.intervals(r, -f32::MAX, f32::MAX)
+            .into_iter()
+            .flat_map(|interval| vec![interval.enter, interval.exit])
+            .find(|hit| hit.t() > t_min && hit.t() < t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let a = self.a.bounding_box();
+        match self.operation {
+            Operation::Union => Some(Aabb::surrounding(&a?, &self.b.bounding_box()?)),
+            Operation::Intersection => a.or_else(|| self.b.bounding_box()),
+            Operation::Difference => a,
+        }
+    }
+
+    fn intervals(&self, r: &Ray, t_min: f32, t_max: f32) -> Vec<Interval<'_>> {
+        // The spans of the children must be found from outside of them, so
+        // look along the whole stretch of the ray through both their boxes.
+        let (lo, hi) = match (self.a.bounding_box(), self.b.bounding_box()) {
+            (Some(a), Some(b)) => match Aabb::surrounding(&a, &b).range(r, -f32::MAX, f32::MAX) {
+                Some((t0, t1)) => {
+                    let pad = 1e-4 * (t1 - t0).max(1.0);
+                    (t0 - pad, t1 + pad)
+                }
+                None => return vec![],
+            },
+            _ => (t_min, t_max),
+        };
+
+        // Every crossing of either surface, with whether it belongs to b and
+        // whether it goes in.
+        let mut events = vec![];
+        for (intervals, in_b) in [
+            (self.a.intervals(r, lo, hi), false),
+            (self.b.intervals(r, lo, hi), true),
+        ] {
+            for interval in intervals {
+                events.push((interval.enter, in_b, true));
+                events.push((interval.exit, in_b, false));
+            }
+        }
+        events.sort_by(|x, y| x.0.t().total_cmp(&y.0.t()));
+
+        let mut intervals = vec![];
+        let mut enter = None;
+        let (mut in_a, mut in_b) = (false, false);
+        for (hit, of_b, entering) in events {
+            let before = self.operation.inside(in_a, in_b);
+            if of_b {
+                in_b = entering;
+            } else {
+                in_a = entering;
+            }
+            let after = self.operation.inside(in_a, in_b);
+            if before == after {
+                continue;
+            }
+            let hit = if of_b && self.operation == Operation::Difference {
+                let (normal, shading_normal) = (-*hit.normal(), -*hit.shading_normal());
+                hit.with_normal(normal).with_shading_normal(shading_normal)
+            } else {
+                hit
+            };
+            match enter.take() {
+                None => enter = Some(hit),
+                Some(enter) => intervals.push(Interval { enter, exit: hit }),
+            }
+        }
+        intervals.retain(|i| i.enter.t() > t_min && i.exit.t() < t_max);
+        intervals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Csg;
+    use crate::{
+        hitable::{Hitable, Sphere},
+        material::Dielectric,
+        ray::Ray,
+        rect::Cuboid,
+        test_support::grey,
+        vec3::Vec3,
+    };
+
+    fn sphere(x: f32, radius: f32) -> Sphere {
+        Sphere::new(Vec3::new(x, 0.0, 0.0), radius, grey())
+    }
+
+    fn along_x() -> Ray {
+        Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0))
+    }
+
+    fn spans<H: Hitable>(h: &H, r: &Ray) -> Vec<(f32, f32)> {
+        h.intervals(r, 0.0, f32::MAX)
+            .iter()
+            .map(|i| (i.enter.t(), i.exit.t()))
+            .collect()
+    }
+
+    #[test]
+    fn test_operations() {
+        let r = along_x();
+        // Spheres over x in [-1, 1] and [0, 2].
+        let union = Csg::union(sphere(0.0, 1.0), sphere(1.0, 1.0));
+        assert_eq!(vec![(4.0, 7.0)], spans(&union, &r));
+        let lens = Csg::intersection(sphere(0.0, 1.0), sphere(1.0, 1.0));
+        assert_eq!(vec![(5.0, 6.0)], spans(&lens, &r));
+        let bitten = Csg::difference(sphere(0.0, 1.0), sphere(1.0, 1.0));
+        assert_eq!(vec![(4.0, 5.0)], spans(&bitten, &r));
+
+        let hit = bitten.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(4.0, hit.t());
+        // Out through the bite, facing out of the remaining sphere.
+        let hit = bitten.hit(&r, 4.5, f32::MAX).unwrap();
+        assert_eq!(5.0, hit.t());
+        assert_eq!(&Vec3::new(1.0, 0.0, 0.0), hit.normal());
+        assert!(bitten.hit(&r, 5.5, f32::MAX).is_none());
+
+        let bbox = lens.bounding_box().unwrap();
+        assert_eq!(&Vec3::new(-1.0, -1.0, -1.0), bbox.min());
+        assert_eq!(
+            &Vec3::new(2.0, 1.0, 1.0),
+            union.bounding_box().unwrap().max()
+        );
+    }
+
+    #[test]
+    fn test_hollow_and_nested() {
+        let r = along_x();
+        let shell = Csg::difference(
+            Sphere::new(Vec3::zero(), 1.0, Box::new(Dielectric::new(1.5))),
+            Sphere::new(Vec3::zero(), 0.5, Box::new(Dielectric::new(1.5))),
+        );
+        assert_eq!(vec![(4.0, 4.5), (5.5, 6.0)], spans(&shell, &r));
+        // From the cavity the shell's inner wall faces the ray.
+        let inside = Ray::new(Vec3::zero(), Vec3::new(1.0, 0.0, 0.0));
+        let hit = shell.hit(&inside, 0.001, f32::MAX).unwrap();
+        assert_eq!(0.5, hit.t());
+        assert_eq!(&Vec3::new(-1.0, 0.0, 0.0), hit.normal());
+
+        // A box drilled through along x, hit on the default interval walk.
+        let drilled = Csg::difference(
+            Cuboid::new(
+                Vec3::new(-1.0, -1.0, -1.0),
+                Vec3::new(1.0, 1.0, 1.0),
+                grey(),
+            ),
+            Csg::union(sphere(-1.0, 0.5), sphere(1.0, 0.5)),
+        );
+        assert_eq!(vec![(4.5, 5.5)], spans(&drilled, &r));
+        let off_axis = Ray::new(Vec3::new(-5.0, 0.8, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(vec![(4.0, 6.0)], spans(&drilled, &off_axis));
+    }
+}
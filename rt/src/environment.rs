@@ -0,0 +1,303 @@
+use crate::{image::Image, sampler::Sampler, vec3::Vec3};
+use rand::Rng;
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+
+// Radiance arriving from infinitely far away along a direction that missed
+// every object in the scene.
+pub trait Environment: Send + Sync {
+    fn color(&self, dir: &Vec3) -> Vec3;
+
+    // Picks a unit direction in proportion to the incoming light, returning it
+    // with its radiance and solid angle pdf. Environments that return None are
+    // only ever found by rays bouncing off surfaces.
+    fn sample(&self, _sampler: &mut Sampler) -> Option<(Vec3, Vec3, f32)> {
+        None
+    }
+
+    // The solid angle density with which `sample` returns `dir`.
+    fn pdf(&self, _dir: &Vec3) -> f32 {
+        0.0
+    }
+}
+
+pub struct ConstantColor {
+    color: Vec3,
+}
+
+impl ConstantColor {
+    pub fn new(color: Vec3) -> Self {
+        ConstantColor { color }
+    }
+}
+
+impl Environment for ConstantColor {
+    fn color(&self, _: &Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+pub struct Gradient {
+    bottom: Vec3,
+    top: Vec3,
+}
+
+impl Gradient {
+    pub fn new(bottom: Vec3, top: Vec3) -> Self {
+        Gradient { bottom, top }
+    }
+
+    pub fn sky() -> Self {
+        Gradient::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.5, 0.7, 1.0))
+    }
+}
+
+impl Environment for Gradient {
+    fn color(&self, dir: &Vec3) -> Vec3 {
+        let t = 0.5 * (dir.unit_vector().y() + 1.0);
+        (1.0 - t) * self.bottom + t * self.top
+    }
+}
+
+// A latitude-longitude map with +y at the top row and -z at the center column.
+pub struct Equirectangular {
+    image: Image,
+    rows: Distribution,
+    columns: Vec<Distribution>,
+}
+
+impl Equirectangular {
+    pub fn new(image: Image) -> Self {
+        let (width, height) = (image.width(), image.height());
+        // Lookups are bilinear, so a texel's weight has to cover the brightest
+        // of its neighbours to keep the pdf non-zero wherever light arrives.
+        let columns: Vec<Distribution> = (0..height)
+            .map(|y| {
+                let sin_theta = ((y as f32 + 0.5) / height as f32 * PI).sin();
+                let func = (0..width)
+                    .map(|x| {
+                        let mut lum = 0.0f32;
+                        for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                            for dx in 0..3 {
+                                let nx = (x + width + dx - 1) % width;
+                                lum = lum.max(image.pixel(nx, ny).luminance());
+                            }
+                        }
+                        lum * sin_theta
+                    })
+                    .collect();
+                Distribution::new(func)
+            })
+            .collect();
+        let rows = Distribution::new(columns.iter().map(|c| c.integral).collect());
+        Equirectangular {
+            image,
+            rows,
+            columns,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Equirectangular::new(Image::load(path)?))
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+}
+
+impl Environment for Equirectangular {
+    fn color(&self, dir: &Vec3) -> Vec3 {
+        let (u, v) = direction_to_uv(dir);
+        let (width, height) = (self.image.width(), self.image.height());
+        let x = u * width as f32 - 0.5;
+        let y = (v * height as f32 - 0.5).clamp(0.0, (height - 1) as f32);
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let x0 = (x.floor() as isize).rem_euclid(width as isize) as usize;
+        let x1 = (x0 + 1) % width;
+        let y0 = y as usize;
+        let y1 = (y0 + 1).min(height - 1);
+        let top = (1.0 - fx) * self.image.pixel(x0, y0) + fx * self.image.pixel(x1, y0);
+        let bottom = (1.0 - fx) * self.image.pixel(x0, y1) + fx * self.image.pixel(x1, y1);
+        (1.0 - fy) * top + fy * bottom
+    }
+
+    fn sample(&self, sampler: &mut Sampler) -> Option<(Vec3, Vec3, f32)> {
+        if self.rows.integral <= 0.0 {
+            return None;
+        }
+        let (v, row_pdf, row) = self.rows.sample(sampler.gen());
+        let (u, column_pdf, _) = self.columns[row].sample(sampler.gen());
+        let dir = uv_to_direction(u, v);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return None;
+        }
+        let pdf = row_pdf * column_pdf / (2.0 * PI * PI * sin_theta);
+        Some((dir, self.color(&dir), pdf))
+    }
+
+    fn pdf(&self, dir: &Vec3) -> f32 {
+        let (u, v) = direction_to_uv(dir);
+        let sin_theta = (v * PI).sin();
+        if self.rows.integral <= 0.0 || sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let x = ((u * self.image.width() as f32) as usize).min(self.image.width() - 1);
+        let y = ((v * self.image.height() as f32) as usize).min(self.image.height() - 1);
+        self.columns[y].func[x] / self.rows.integral / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+// A piecewise constant density over [0, 1) sampled by inverting its CDF.
+struct Distribution {
+    func: Vec<f32>,
+    cdf: Vec<f32>,
+    integral: f32,
+}
+
+impl Distribution {
+    fn new(func: Vec<f32>) -> Self {
+        let n = func.len();
+        let mut cdf = Vec::with_capacity(n + 1);
+        cdf.push(0.0);
+        for (i, f) in func.iter().enumerate() {
+            cdf.push(cdf[i] + f / n as f32);
+        }
+        let integral = cdf[n];
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f32 / n as f32
+            };
+        }
+        Distribution {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    // Returns the sampled point, its density and the segment it falls in.
+    fn sample(&self, u: f32) -> (f32, f32, usize) {
+        let n = self.func.len();
+        let offset = (self.cdf.partition_point(|&c| c <= u) - 1).min(n - 1);
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 {
+            (u - self.cdf[offset]) / width
+        } else {
+            0.0
+        };
+        let pdf = if self.integral > 0.0 {
+            self.func[offset] / self.integral
+        } else {
+            1.0
+        };
+        (
+            ((offset as f32 + du) / n as f32).min(1.0 - f32::EPSILON),
+            pdf,
+            offset,
+        )
+    }
+}
+
+pub fn direction_to_uv(dir: &Vec3) -> (f32, f32) {
+    let d = dir.unit_vector();
+    let u = 0.5 + d.x().atan2(-d.z()) / (2.0 * PI);
+    let v = d.y().clamp(-1.0, 1.0).acos() / PI;
+    (u, v)
+}
+
+pub fn uv_to_direction(u: f32, v: f32) -> Vec3 {
+    let (sin_theta, cos_theta) = (v * PI).sin_cos();
+    let (sin_phi, cos_phi) = ((u - 0.5) * 2.0 * PI).sin_cos();
+    Vec3::new(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        direction_to_uv, uv_to_direction, ConstantColor, Environment, Equirectangular, Gradient,
+    };
+    use crate::{image::Image, sampler::Sampler, vec3::Vec3};
+
+    #[test]
+    fn test_constant_and_gradient() {
+        let c = ConstantColor::new(Vec3::new(0.1, 0.2, 0.3));
+        assert_eq!(Vec3::new(0.1, 0.2, 0.3), c.color(&Vec3::new(0.0, 1.0, 0.0)));
+        let sky = Gradient::sky();
+        assert_eq!(
+            Vec3::new(0.5, 0.7, 1.0),
+            sky.color(&Vec3::new(0.0, 2.0, 0.0))
+        );
+        assert_eq!(
+            Vec3::new(1.0, 1.0, 1.0),
+            sky.color(&Vec3::new(0.0, -1.0, 0.0))
+        );
+    }
+
+    #[test]
+    fn test_equirectangular_lookup() {
+        assert_eq!((0.5, 0.5), direction_to_uv(&Vec3::new(0.0, 0.0, -1.0)));
+        let (u, v) = direction_to_uv(&Vec3::new(1.0, 0.0, 0.0));
+        assert!((u - 0.75).abs() < 1e-6 && (v - 0.5).abs() < 1e-6);
+
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let blue = Vec3::new(0.0, 0.0, 1.0);
+        let map = Equirectangular::new(Image::from_pixels(
+            4,
+            2,
+            vec![red, red, red, red, blue, blue, blue, blue],
+        ));
+        assert_eq!(red, map.color(&Vec3::new(0.0, 1.0, 0.0)));
+        assert_eq!(blue, map.color(&Vec3::new(0.3, -1.0, 0.0)));
+    }
+
+    #[test]
+    fn test_uv_round_trip() {
+        for &(u, v) in &[(0.1, 0.2), (0.5, 0.5), (0.9, 0.7), (0.3, 0.95)] {
+            let (u2, v2) = direction_to_uv(&uv_to_direction(u, v));
+            assert!((u - u2).abs() < 1e-5 && (v - v2).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_importance_sampling() {
+        // A dim map with one bright texel standing in for the sun.
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Vec3::new(0.1, 0.1, 0.1); width * height];
+        pixels[2 * width + 5] = Vec3::new(1000.0, 1000.0, 1000.0);
+        let map = Equirectangular::new(Image::from_pixels(width, height, pixels));
+        let mut sampler = Sampler::new(5);
+        let mut near_sun = 0;
+        for _ in 0..1000 {
+            let (dir, radiance, pdf) = map.sample(&mut sampler).unwrap();
+            assert!((dir.length() - 1.0).abs() < 1e-5);
+            assert!((map.pdf(&dir) - pdf).abs() <= 1e-3 * pdf);
+            assert_eq!(map.color(&dir), radiance);
+            let (u, v) = direction_to_uv(&dir);
+            if (u * width as f32 - 5.5).abs() < 2.0 && (v * height as f32 - 2.5).abs() < 2.0 {
+                near_sun += 1;
+            }
+        }
+        assert!(near_sun > 900);
+
+        // The pdf has to integrate to one over the sphere.
+        let n = 400;
+        let mut total = 0.0;
+        for j in 0..n {
+            for i in 0..2 * n {
+                let (u, v) = (
+                    (i as f32 + 0.5) / (2 * n) as f32,
+                    (j as f32 + 0.5) / n as f32,
+                );
+                let d_omega = (v * std::f32::consts::PI).sin() * 2.0 * std::f32::consts::PI.powi(2)
+                    / (2 * n * n) as f32;
+                total += map.pdf(&uv_to_direction(u, v)) * d_omega;
+            }
+        }
+        assert!((total - 1.0).abs() < 1e-2, "pdf integrates to {}", total);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::{
+    camera::Camera,
+    hitable::HitableList,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    mesh::TriangleMesh,
+    transform::Mat4,
+    vec3::Vec3,
+};
+use ::gltf::{camera::Projection, material::AlphaMode, mesh::Mode, Document, Node};
+use std::io;
+use std::path::Path;
+
+// The meshes of a glTF scene, with the node transforms baked into their
+// vertices, and its perspective cameras.
+pub struct GltfScene {
+    pub world: HitableList,
+    pub cameras: Vec<Camera>,
+}
+
+// `aspect` is that of the image to be rendered; the cameras keep their
+// vertical field of view.
+pub fn load_gltf<P: AsRef<Path>>(path: P, aspect: f32) -> io::Result<GltfScene> {
+    let (document, buffers, _) = ::gltf::import(path).map_err(io::Error::other)?;
+    build_scene(&document, &buffers, aspect)
+}
+
+// A .glb file or a .gltf file with embedded buffers.
+pub fn read_gltf(data: &[u8], aspect: f32) -> io::Result<GltfScene> {
+    let (document, buffers, _) = ::gltf::import_slice(data).map_err(io::Error::other)?;
+    build_scene(&document, &buffers, aspect)
+}
+
+fn build_scene(
+    document: &Document,
+    buffers: &[::gltf::buffer::Data],
+    aspect: f32,
+) -> io::Result<GltfScene> {
+    let mut scene = GltfScene {
+        world: HitableList::new(),
+        cameras: vec![],
+    };
+    if let Some(root) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        for node in root.nodes() {
+            add_node(&mut scene, &node, &Mat4::identity(), buffers, aspect)?;
+        }
+    }
+    Ok(scene)
+}
+
+fn add_node(
+    scene: &mut GltfScene,
+    node: &Node,
+    parent: &Mat4,
+    buffers: &[::gltf::buffer::Data],
+    aspect: f32,
+) -> io::Result<()> {
+    let transform = *parent * Mat4::from_columns(node.transform().matrix());
+    if let Some(mesh) = node.mesh() {
+        for primitive in mesh.primitives() {
+            if primitive.mode() != Mode::Triangles {
+                continue;
+            }
+            let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+            let vertices: Vec<Vec3> = match reader.read_positions() {
+                Some(positions) => positions
+                    .map(|p| transform.transform_point(&Vec3::new(p[0], p[1], p[2])))
+                    .collect(),
+                None => continue,
+            };
+            let flat: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+            if flat.iter().any(|&i| i as usize >= vertices.len()) {
+                return Err(invalid("vertex index out of range"));
+            }
+            let vertex_count = vertices.len();
+            let indices = flat.chunks_exact(3).map(|t| [t[0], t[1], t[2]]).collect();
+            let material = to_material(&primitive.material());
+            let mut mesh = TriangleMesh::new(vertices, indices, material);
+            if let Some(normals) = reader.read_normals() {
+                // Normals go through the inverse transpose.
+                let normal_matrix = transform
+                    .inverse()
+                    .unwrap_or_else(Mat4::identity)
+                    .transpose();
+                let normals = normals
+                    .map(|n| {
+                        normal_matrix
+                            .transform_vector(&Vec3::new(n[0], n[1], n[2]))
+                            .unit_vector()
+                    })
+                    .collect();
+                mesh = mesh.with_normals(per_vertex(normals, vertex_count, "NORMAL")?);
+            }
+            if let Some(uvs) = reader.read_tex_coords(0) {
+                let uvs = uvs.into_f32().collect();
+                mesh = mesh.with_uvs(per_vertex(uvs, vertex_count, "TEXCOORD_0")?);
+            }
+            if let Some(colors) = reader.read_colors(0) {
+                let colors = colors
+                    .into_rgb_f32()
+                    .map(|c| Vec3::new(c[0], c[1], c[2]))
+                    .collect();
+                mesh = mesh.with_colors(per_vertex(colors, vertex_count, "COLOR_0")?);
+            }
+            scene.world.push(Box::new(mesh));
+        }
+    }
+    if let Some(camera) = node.camera() {
+        if let Projection::Perspective(perspective) = camera.projection() {
+            // Cameras look down their local -z with +y up.
+            let origin = transform.transform_point(&Vec3::zero());
+            let forward = transform.transform_vector(&Vec3::new(0.0, 0.0, -1.0));
+            let up = transform.transform_vector(&Vec3::new(0.0, 1.0, 0.0));
+            scene.cameras.push(Camera::new(
+                origin,
+                origin + forward,
+                up,
+                perspective.yfov().to_degrees(),
+                aspect,
+                0.0,
+                1.0,
+            ));
+        }
+    }
+    for child in node.children() {
+        add_node(scene, &child, &transform, buffers, aspect)?;
+    }
+    Ok(())
+}
+
+// Attributes other than POSITION have to match it value for value.
+fn per_vertex<T>(values: Vec<T>, vertex_count: usize, name: &str) -> io::Result<Vec<T>> {
+    if values.len() != vertex_count {
+        return Err(invalid(&format!("{} count differs from POSITION", name)));
+    }
+    Ok(values)
+}
+
+// Emissive materials become lights, transmissive or blended ones glass,
+// mostly metallic ones metal with their roughness as fuzz, and the rest
+// diffuse. Textures are ignored.
+fn to_material(material: &::gltf::Material) -> Box<dyn Material> {
+    let pbr = material.pbr_metallic_roughness();
+    let [r, g, b, alpha] = pbr.base_color_factor();
+    let base_color = Vec3::new(r, g, b);
+    let [er, eg, eb] = material.emissive_factor();
+    let emission = Vec3::new(er, eg, eb) * material.emissive_strength().unwrap_or(1.0);
+    let transmission = material
+        .transmission()
+        .map_or(0.0, |t| t.transmission_factor());
+    if emission != Vec3::zero() {
+        Box::new(DiffuseLight::new(emission))
+    } else if transmission > 0.5 || (material.alpha_mode() == AlphaMode::Blend && alpha < 1.0) {
+        Box::new(Dielectric::new(material.ior().unwrap_or(1.5)))
+    } else if pbr.metallic_factor() >= 0.5 {
+        Box::new(Metal::new(base_color, pbr.roughness_factor()))
+    } else {
+        Box::new(Lambertian::new(base_color))
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_gltf;
+    use crate::{hitable::Hitable, ray::Ray, sampler::Sampler, vec3::Vec3};
+
+    // A triangle scaled by a child node and moved by its parent, lit by its
+    // emissive material, and a camera one unit in front of the origin.
+    const JSON: &str = r#"{
+        "asset": {"version": "2.0"},
+        "scene": 0,
+        "scenes": [{"nodes": [0, 2]}],
+        "nodes": [
+            {"translation": [0, 0, -5], "children": [1]},
+            {"scale": [2, 2, 2], "mesh": 0},
+            {"translation": [0, 0, 1], "camera": 0}
+        ],
+        "cameras": [{"type": "perspective", "perspective": {"yfov": 1.0, "znear": 0.1}}],
+        "meshes": [{"primitives": [{"attributes": {"POSITION": 0}, "indices": 1, "material": 0}]}],
+        "materials": [{"emissiveFactor": [1, 0.5, 0.25]}],
+        "buffers": [{"byteLength": 44}],
+        "bufferViews": [
+            {"buffer": 0, "byteOffset": 0, "byteLength": 36},
+            {"buffer": 0, "byteOffset": 36, "byteLength": 6}
+        ],
+        "accessors": [
+            {"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+             "min": [-1, -1, 0], "max": [1, 1, 0]},
+            {"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}
+        ]
+    }"#;
+
+    fn glb() -> Vec<u8> {
+        glb_with(JSON)
+    }
+
+    fn glb_with(json: &str) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+        let mut bin = vec![];
+        for x in [-1.0f32, -1.0, 0.0, 1.0, -1.0, 0.0, 0.0, 1.0, 0.0].iter() {
+            bin.extend_from_slice(&x.to_le_bytes());
+        }
+        for i in 0..3u16 {
+            bin.extend_from_slice(&i.to_le_bytes());
+        }
+        bin.extend_from_slice(&[0, 0]);
+
+        let mut data = b"glTF".to_vec();
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"JSON");
+        data.extend_from_slice(&json);
+        data.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"BIN\0");
+        data.extend_from_slice(&bin);
+        data
+    }
+
+    #[test]
+    fn test_read_glb() {
+        let scene = read_gltf(&glb(), 1.0).unwrap();
+        assert_eq!(1, scene.world.len());
+        assert_eq!(1, scene.cameras.len());
+
+        // Only inside the triangle once it has been scaled.
+        let r = Ray::new(Vec3::new(1.5, -1.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = scene.world.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(5.0, hit.t());
+        assert_eq!(&Vec3::new(0.0, 0.0, 1.0), hit.normal());
+        assert_eq!(Vec3::new(1.0, 0.5, 0.25), hit.material().emitted(&r, &hit));
+
+        let r = scene.cameras[0].get_ray(0.5, 0.5, &mut Sampler::new(0));
+        assert_eq!(&Vec3::new(0.0, 0.0, 1.0), r.origin());
+        assert!((r.direction().unit_vector() - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-6);
+        let hit = scene.world.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(6.0, hit.t() * r.direction().length());
+    }
+
+    #[test]
+    fn test_reject_garbage() {
+        assert!(read_gltf(b"not gltf", 1.0).is_err());
+        let mut data = glb();
+        // Point the last index past the vertices.
+        let at = data.len() - 4;
+        data[at] = 3;
+        assert!(read_gltf(&data, 1.0).is_err());
+        // Two normals for three vertices.
+        let short_normals = JSON
+            .replace(r#""POSITION": 0}"#, r#""POSITION": 0, "NORMAL": 2}"#)
+            .replace(
+                r#""type": "SCALAR"}"#,
+                r#""type": "SCALAR"},
+            {"bufferView": 0, "componentType": 5126, "count": 2, "type": "VEC3"}"#,
+            );
+        assert!(read_gltf(&glb_with(&short_normals), 1.0).is_err());
+    }
+}
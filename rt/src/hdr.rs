@@ -0,0 +1,306 @@
+use crate::{image::Image, vec3::Vec3};
+use std::io::{self, BufRead, Read, Write};
+
+// Reads a Radiance RGBE image.
+pub fn read_hdr<R: BufRead>(mut reader: R) -> io::Result<Image> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid("not a Radiance HDR file"));
+    }
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("unexpected end of header"));
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(invalid("unsupported pixel format"));
+            }
+        }
+    }
+    line.clear();
+    reader.read_line(&mut line)?;
+    let (width, height) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", h, "+X", w] => (parse_dim(w)?, parse_dim(h)?),
+        _ => return Err(invalid("unsupported image orientation")),
+    };
+
+    width
+        .checked_mul(height)
+        .ok_or_else(|| invalid("image too large"))?;
+    // Both grow only as far as the data goes.
+    let mut pixels = Vec::new();
+    let mut scanline = Vec::new();
+    for _ in 0..height {
+        read_scanline(&mut reader, width, &mut scanline)?;
+        pixels.extend(scanline.iter().map(rgbe_to_vec3));
+    }
+    Ok(Image::from_pixels(width, height, pixels))
+}
+
+pub fn write_hdr<W: Write>(out: &mut W, image: &Image) -> io::Result<()> {
+    let width = image.width();
+    if width == 0 || image.height() == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot write an empty image",
+        ));
+    }
+    write!(
+        out,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height(),
+        width
+    )?;
+    let mut channel = Vec::with_capacity(width);
+    for row in image.pixels().chunks(width) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(vec3_to_rgbe).collect();
+        if !(8..0x8000).contains(&width) {
+            for pixel in rgbe.iter() {
+                out.write_all(pixel)?;
+            }
+            continue;
+        }
+        out.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+        for c in 0..4 {
+            channel.clear();
+            channel.extend(rgbe.iter().map(|pixel| pixel[c]));
+            write_rle_channel(out, &channel)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_rle_channel<W: Write>(out: &mut W, data: &[u8]) -> io::Result<()> {
+    const MIN_RUN: usize = 4;
+    let mut x = 0;
+    while x < data.len() {
+        // Find the next run long enough to be worth encoding.
+        let mut run_start = x;
+        let mut run_len = 0;
+        while run_start < data.len() {
+            run_len = 1;
+            while run_len < 127
+                && run_start + run_len < data.len()
+                && data[run_start + run_len] == data[run_start]
+            {
+                run_len += 1;
+            }
+            if run_len >= MIN_RUN {
+                break;
+            }
+            run_start += run_len;
+        }
+        // Everything before it goes out as literals.
+        while x < run_start.min(data.len()) {
+            let count = (run_start - x).min(128);
+            out.write_all(&[count as u8])?;
+            out.write_all(&data[x..x + count])?;
+            x += count;
+        }
+        if run_start < data.len() {
+            out.write_all(&[128 + run_len as u8, data[run_start]])?;
+            x = run_start + run_len;
+        }
+    }
+    Ok(())
+}
+
+fn read_scanline<R: Read>(
+    reader: &mut R,
+    width: usize,
+    scanline: &mut Vec<[u8; 4]>,
+) -> io::Result<()> {
+    scanline.clear();
+    let mut rgbe = [0u8; 4];
+    reader.read_exact(&mut rgbe)?;
+    let is_rle = (8..0x8000).contains(&width)
+        && rgbe[0] == 2
+        && rgbe[1] == 2
+        && ((rgbe[2] as usize) << 8 | rgbe[3] as usize) == width;
+    if !is_rle {
+        scanline.push(rgbe);
+        return read_flat_scanline(reader, width, scanline);
+    }
+    scanline.resize(width, [0; 4]);
+    // Adaptive run length encoding: each channel is stored separately.
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+            let (run, count) = if count[0] > 128 {
+                (true, count[0] as usize - 128)
+            } else {
+                (false, count[0] as usize)
+            };
+            if count == 0 || x + count > width {
+                return Err(invalid("bad scanline run length"));
+            }
+            if run {
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                for pixel in &mut scanline[x..x + count] {
+                    pixel[channel] = value[0];
+                }
+            } else {
+                let mut values = [0u8; 128];
+                reader.read_exact(&mut values[..count])?;
+                for (pixel, value) in scanline[x..x + count].iter_mut().zip(values.iter()) {
+                    pixel[channel] = *value;
+                }
+            }
+            x += count;
+        }
+    }
+    Ok(())
+}
+
+// Uncompressed pixels, possibly with the original (pre-1991) run encoding in
+// which a pixel of 1, 1, 1 repeats the previous one.
+fn read_flat_scanline<R: Read>(
+    reader: &mut R,
+    width: usize,
+    scanline: &mut Vec<[u8; 4]>,
+) -> io::Result<()> {
+    let mut shift = 0u32;
+    while scanline.len() < width {
+        let mut rgbe = [0u8; 4];
+        reader.read_exact(&mut rgbe)?;
+        if rgbe[0] == 1 && rgbe[1] == 1 && rgbe[2] == 1 {
+            // Consecutive markers make up ever higher bytes of the count.
+            if rgbe[3] == 0 || shift > usize::BITS - 8 {
+                return Err(invalid("bad scanline run length"));
+            }
+            let count = (rgbe[3] as usize) << shift;
+            if count > width - scanline.len() {
+                return Err(invalid("bad scanline run length"));
+            }
+            let previous = scanline[scanline.len() - 1];
+            scanline.resize(scanline.len() + count, previous);
+            shift += 8;
+        } else {
+            scanline.push(rgbe);
+            shift = 0;
+        }
+    }
+    Ok(())
+}
+
+fn rgbe_to_vec3(rgbe: &[u8; 4]) -> Vec3 {
+    if rgbe[3] == 0 {
+        return Vec3::zero();
+    }
+    let scale = 2f32.powi(rgbe[3] as i32 - (128 + 8));
+    Vec3::new(
+        (rgbe[0] as f32 + 0.5) * scale,
+        (rgbe[1] as f32 + 0.5) * scale,
+        (rgbe[2] as f32 + 0.5) * scale,
+    )
+}
+
+fn vec3_to_rgbe(col: &Vec3) -> [u8; 4] {
+    let finite = |c: f32| if c > 0.0 { c.min(f32::MAX) } else { 0.0 };
+    let (r, g, b) = (finite(col.r()), finite(col.g()), finite(col.b()));
+    let v = r.max(g).max(b);
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // v = m * 2^e with m in [0.5, 1), as far as the exponent byte reaches.
+    let e = (v.log2().floor() as i32 + 1).clamp(-128, 127);
+    let scale = 256.0 / 2f32.powi(e);
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        (e + 128) as u8,
+    ]
+}
+
+pub(crate) fn parse_dim(s: &str) -> io::Result<usize> {
+    match s.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(invalid("bad image dimensions")),
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_hdr, vec3_to_rgbe, write_hdr, write_rle_channel};
+    use crate::{image::Image, vec3::Vec3};
+
+    #[test]
+    fn test_read_flat() {
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 2\n".to_vec();
+        data.extend_from_slice(&[128, 64, 0, 129, 0, 0, 0, 0]);
+        let image = read_hdr(&data[..]).unwrap();
+        assert_eq!((2, 1), (image.width(), image.height()));
+        assert_eq!(Vec3::new(128.5, 64.5, 0.5) / 128.0, image.pixel(0, 0));
+        assert_eq!(Vec3::zero(), image.pixel(1, 0));
+    }
+
+    #[test]
+    fn test_read_rle() {
+        let mut data = b"#?RADIANCE\n\n-Y 2 +X 8\n".to_vec();
+        for _ in 0..2 {
+            data.extend_from_slice(&[2, 2, 0, 8]);
+            // red: a run of eight, green: eight literals, blue: two runs
+            data.extend_from_slice(&[136, 255]);
+            data.extend_from_slice(&[8, 0, 1, 2, 3, 4, 5, 6, 7]);
+            data.extend_from_slice(&[132, 0, 132, 127]);
+            data.extend_from_slice(&[136, 128]);
+        }
+        let image = read_hdr(&data[..]).unwrap();
+        assert_eq!((8, 2), (image.width(), image.height()));
+        assert_eq!(Vec3::new(255.5, 3.5, 0.5) / 256.0, image.pixel(3, 0));
+        assert_eq!(Vec3::new(255.5, 7.5, 127.5) / 256.0, image.pixel(7, 1));
+    }
+
+    #[test]
+    fn test_reject_garbage() {
+        assert!(read_hdr(&b"P6\n1 1\n255\n"[..]).is_err());
+        assert!(read_hdr(&b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0"[..]).is_err());
+        assert!(read_hdr(&b"#?RADIANCE\n\n-Y 1 +X 2\n\0\0\0\0"[..]).is_err());
+        assert!(write_hdr(&mut vec![], &Image::new(0, 0)).is_err());
+        assert!(read_hdr(&b"#?RADIANCE\n\n-Y 4000000000 +X 4000000000\n"[..]).is_err());
+        assert!(read_hdr(&b"#?RADIANCE\n\n-Y 18446744073709551615 +X 2\n"[..]).is_err());
+        // Old style runs of nothing, enough to shift a count out of range.
+        let mut data = b"#?RADIANCE\n\n-Y 1 +X 4\n".to_vec();
+        data.extend_from_slice(&[128, 64, 0, 129]);
+        for _ in 0..9 {
+            data.extend_from_slice(&[1, 1, 1, 0]);
+        }
+        assert!(read_hdr(&data[..]).is_err());
+    }
+
+    #[test]
+    fn test_encode_extremes() {
+        let inf = f32::INFINITY;
+        assert_eq!(
+            [255, 0, 0, 255],
+            vec3_to_rgbe(&Vec3::new(inf, 0.0, f32::NAN))
+        );
+        assert_eq!(
+            [255, 128, 0, 255],
+            vec3_to_rgbe(&Vec3::new(f32::MAX, 2f32.powi(126), -1.0))
+        );
+        assert_eq!([0, 0, 0, 0], vec3_to_rgbe(&Vec3::new(f32::NAN, -inf, 0.0)));
+    }
+
+    #[test]
+    fn test_rle_channel() {
+        let data = [1, 2, 3, 3, 3, 3, 3, 4, 5, 5];
+        let mut out = vec![];
+        write_rle_channel(&mut out, &data).unwrap();
+        assert_eq!(vec![2, 1, 2, 133, 3, 3, 4, 5, 5], out);
+    }
+}
@@ -1,13 +1,70 @@
-use crate::{material::Material, ray::Ray, vec3::Vec3};
+use crate::{aabb::Aabb, material::Material, ray::Ray, texture::Footprint, vec3::Vec3};
+use std::f32::consts::PI;
+use std::sync::Arc;
 
 pub struct HitRecord<'a> {
     t: f32,
     p: Vec3,
     normal: Vec3,
+    shading_normal: Vec3,
+    u: f32,
+    v: f32,
+    dpdu: Vec3,
+    dpdv: Vec3,
+    color: Vec3,
     material: &'a dyn Material,
 }
 
 impl<'a> HitRecord<'a> {
+    pub(crate) fn new(t: f32, p: Vec3, normal: Vec3, material: &'a dyn Material) -> Self {
+        HitRecord {
+            t,
+            p,
+            normal,
+            shading_normal: normal,
+            u: 0.0,
+            v: 0.0,
+            dpdu: Vec3::zero(),
+            dpdv: Vec3::zero(),
+            color: Vec3::new(1.0, 1.0, 1.0),
+            material,
+        }
+    }
+
+    pub(crate) fn with_p(mut self, p: Vec3) -> Self {
+        self.p = p;
+        self
+    }
+
+    // Sets both normals.
+    pub(crate) fn with_normal(mut self, normal: Vec3) -> Self {
+        self.normal = normal;
+        self.shading_normal = normal;
+        self
+    }
+
+    pub(crate) fn with_shading_normal(mut self, shading_normal: Vec3) -> Self {
+        self.shading_normal = shading_normal;
+        self
+    }
+
+    pub(crate) fn with_uv(mut self, u: f32, v: f32) -> Self {
+        self.u = u;
+        self.v = v;
+        self
+    }
+
+    pub(crate) fn with_derivatives(mut self, dpdu: Vec3, dpdv: Vec3) -> Self {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self
+    }
+
+    pub(crate) fn with_color(mut self, color: Vec3) -> Self {
+        self.color = color;
+        self
+    }
+
     pub fn t(&self) -> f32 {
         self.t
     }
@@ -16,17 +73,178 @@ impl<'a> HitRecord<'a> {
         &self.p
     }
 
+    // The normal of the surface as it is hit, which tells the sides apart.
     pub fn normal(&self) -> &Vec3 {
         &self.normal
     }
 
+    // The normal that materials shade with: interpolated across meshes and
+    // perturbed by bump and normal maps. On the same side as `normal`.
+    pub fn shading_normal(&self) -> &Vec3 {
+        &self.shading_normal
+    }
+
+    // Unit tangent and bitangent around the shading normal, following dpdu
+    // and dpdv where the surface has them.
+    pub fn tangents(&self) -> (Vec3, Vec3) {
+        let n = self.shading_normal;
+        let mut tangent = self.dpdu - Vec3::dot(&self.dpdu, &n) * n;
+        if tangent.squared_length() < 1e-12 {
+            // Any direction across the normal will do.
+            let other = if n.x().abs() > 0.9 {
+                Vec3::new(0.0, 1.0, 0.0)
+            } else {
+                Vec3::new(1.0, 0.0, 0.0)
+            };
+            tangent = Vec3::cross(&other, &n);
+        }
+        let tangent = tangent.unit_vector();
+        let bitangent = Vec3::cross(&n, &tangent);
+        if Vec3::dot(&bitangent, &self.dpdv) < 0.0 {
+            (tangent, -bitangent)
+        } else {
+            (tangent, bitangent)
+        }
+    }
+
+    // Surface coordinates: texture coordinates where the surface has them,
+    // otherwise the barycentrics of the second and third vertex on triangles.
+    pub fn u(&self) -> f32 {
+        self.u
+    }
+
+    pub fn v(&self) -> f32 {
+        self.v
+    }
+
+    // How the point moves with u and v. Zero on surfaces that do not
+    // provide them.
+    pub fn dpdu(&self) -> &Vec3 {
+        &self.dpdu
+    }
+
+    pub fn dpdv(&self) -> &Vec3 {
+        &self.dpdv
+    }
+
+    // Where the ray's differentials cross the plane tangent to the surface
+    // here.
+    pub(crate) fn differential_points(&self, r: &Ray) -> Option<(Vec3, Vec3)> {
+        let d = r.differentials()?;
+        let meet = |origin: &Vec3, direction: &Vec3| {
+            let denom = Vec3::dot(&self.normal, direction);
+            if denom.abs() < 1e-8 {
+                return None;
+            }
+            let t = Vec3::dot(&self.normal, &(self.p - *origin)) / denom;
+            Some(*origin + t * *direction)
+        };
+        Some((
+            meet(&d.rx_origin, &d.rx_direction)?,
+            meet(&d.ry_origin, &d.ry_direction)?,
+        ))
+    }
+
+    // How far u and v change from this pixel to the next, for filtering
+    // textures. None without ray differentials or surface derivatives.
+    pub fn footprint(&self, r: &Ray) -> Option<Footprint> {
+        let (px, py) = self.differential_points(r)?;
+        // Least squares fit of each offset onto dpdu and dpdv.
+        let a = Vec3::dot(&self.dpdu, &self.dpdu);
+        let b = Vec3::dot(&self.dpdu, &self.dpdv);
+        let c = Vec3::dot(&self.dpdv, &self.dpdv);
+        let det = a * c - b * b;
+        if det <= 1e-6 * a * c {
+            return None;
+        }
+        let solve = |dp: Vec3| {
+            let e = Vec3::dot(&dp, &self.dpdu);
+            let f = Vec3::dot(&dp, &self.dpdv);
+            ((c * e - b * f) / det, (a * f - b * e) / det)
+        };
+        let (dudx, dvdx) = solve(px - self.p);
+        let (dudy, dvdy) = solve(py - self.p);
+        Some(Footprint {
+            dudx,
+            dvdx,
+            dudy,
+            dvdy,
+        })
+    }
+
+    // Vertex color of meshes that have one, white everywhere else. Diffuse
+    // materials multiply their albedo by it.
+    pub fn color(&self) -> &Vec3 {
+        &self.color
+    }
+
     pub fn material(&self) -> &'a dyn Material {
         self.material
     }
 }
 
-pub trait Hitable {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord>;
+// A span of a ray inside an object, from where it goes in to where it comes
+// out.
+pub struct Interval<'a> {
+    pub enter: HitRecord<'a>,
+    pub exit: HitRecord<'a>,
+}
+
+pub trait Hitable: Send + Sync {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>>;
+
+    // None for objects that are unbounded and cannot be put into a BVH.
+    fn bounding_box(&self) -> Option<Aabb>;
+
+    // The spans of the ray within (t_min, t_max) that are inside the object,
+    // in order. Only meaningful for closed objects with outward normals; the
+    // default walks from hit to hit and tells entries from exits by the
+    // normal.
+    fn intervals(&self, r: &Ray, t_min: f32, t_max: f32) -> Vec<Interval<'_>> {
+        let mut intervals = vec![];
+        let mut enter = None;
+        let mut t = t_min;
+        while let Some(hit) = self.hit(r, t, t_max) {
+            t = hit.t;
+            if Vec3::dot(&hit.normal, r.direction()) < 0.0 {
+                // Of two entries in a row, e.g. through a mesh edge, keep
+                // the first.
+                enter = enter.or(Some(hit));
+            } else if let Some(enter) = enter.take() {
+                intervals.push(Interval { enter, exit: hit });
+            }
+        }
+        intervals
+    }
+}
+
+impl<H: Hitable + ?Sized> Hitable for Box<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn intervals(&self, r: &Ray, t_min: f32, t_max: f32) -> Vec<Interval<'_>> {
+        (**self).intervals(r, t_min, t_max)
+    }
+}
+
+// Shared objects, for placing one many times with `Transformed`.
+impl<H: Hitable + ?Sized> Hitable for Arc<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+
+    fn intervals(&self, r: &Ray, t_min: f32, t_max: f32) -> Vec<Interval<'_>> {
+        (**self).intervals(r, t_min, t_max)
+    }
 }
 
 #[derive(Default)]
@@ -40,10 +258,22 @@ impl HitableList {
     pub fn push(&mut self, obj: Box<dyn Hitable>) {
         self.0.push(obj);
     }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn into_vec(self) -> Vec<Box<dyn Hitable>> {
+        self.0
+    }
 }
 
 impl Hitable for HitableList {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
         let mut hit = None;
         let mut closest = t_max;
         for obj in self.0.iter() {
@@ -56,6 +286,15 @@ impl Hitable for HitableList {
         }
         hit
     }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut objs = self.0.iter();
+        let mut bbox = objs.next()?.bounding_box()?;
+        for obj in objs {
+            bbox = Aabb::surrounding(&bbox, &obj.bounding_box()?);
+        }
+        Some(bbox)
+    }
 }
 
 pub struct Sphere {
@@ -75,38 +314,304 @@ impl Sphere {
 }
 
 impl Hitable for Sphere {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
-        let oc = *r.origin() - self.center;
-        let center_dist = oc.length();
-        let dir_len = r.direction().length();
-        let t_near = (center_dist - self.radius.abs()) / dir_len;
-        if t_near > t_max {
-            return None;
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_sphere(
+            &self.center,
+            self.radius,
+            self.material.as_ref(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(sphere_bounds(&self.center, self.radius))
+    }
+
+    fn intervals(&self, r: &Ray, t_min: f32, t_max: f32) -> Vec<Interval<'_>> {
+        sphere_intervals(
+            &self.center,
+            self.radius,
+            self.material.as_ref(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+}
+
+// A sphere whose center moves in a straight line from `center0` at `time0` to
+// `center1` at `time1`, holding still before and after.
+pub struct MovingSphere {
+    center0: Vec3,
+    center1: Vec3,
+    time0: f32,
+    time1: f32,
+    radius: f32,
+    material: Box<dyn Material>,
+}
+
+impl MovingSphere {
+    pub fn new(
+        center0: Vec3,
+        center1: Vec3,
+        time0: f32,
+        time1: f32,
+        radius: f32,
+        material: Box<dyn Material>,
+    ) -> Self {
+        MovingSphere {
+            center0,
+            center1,
+            time0,
+            time1,
+            radius,
+            material,
         }
-        let t_far = (center_dist + self.radius.abs()) / dir_len;
-        if t_far < t_min {
-            return None;
+    }
+
+    pub fn center(&self, time: f32) -> Vec3 {
+        if self.time1 <= self.time0 {
+            return self.center0;
         }
-        let a = Vec3::dot(r.direction(), r.direction());
-        let b = Vec3::dot(&oc, r.direction());
-        let c = Vec3::dot(&oc, &oc) - self.radius * self.radius;
-        let discriminant = b * b - a * c;
-        if discriminant > 0.0 {
-            let root = discriminant.sqrt();
-            let mut t = (-b - root) / a;
-            if t >= t_max || t <= t_min {
-                t = (-b + root) / a;
-            }
-            if t < t_max && t > t_min {
-                let hit_point = r.point_at_parameter(t);
-                return Some(HitRecord {
-                    t,
-                    p: hit_point,
-                    normal: (hit_point - self.center) / self.radius,
-                    material: self.material.as_ref(),
-                });
-            }
+        let s = ((time - self.time0) / (self.time1 - self.time0)).clamp(0.0, 1.0);
+        self.center0 + s * (self.center1 - self.center0)
+    }
+}
+
+impl Hitable for MovingSphere {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let center = self.center(r.time());
+        hit_sphere(
+            &center,
+            self.radius,
+            self.material.as_ref(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::surrounding(
+            &sphere_bounds(&self.center0, self.radius),
+            &sphere_bounds(&self.center1, self.radius),
+        ))
+    }
+
+    fn intervals(&self, r: &Ray, t_min: f32, t_max: f32) -> Vec<Interval<'_>> {
+        let center = self.center(r.time());
+        sphere_intervals(
+            &center,
+            self.radius,
+            self.material.as_ref(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+}
+
+fn hit_sphere<'a>(
+    center: &Vec3,
+    radius: f32,
+    material: &'a dyn Material,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord<'a>> {
+    let oc = *r.origin() - *center;
+    let center_dist = oc.length();
+    let dir_len = r.direction().length();
+    let t_near = (center_dist - radius.abs()) / dir_len;
+    if t_near > t_max {
+        return None;
+    }
+    let t_far = (center_dist + radius.abs()) / dir_len;
+    if t_far < t_min {
+        return None;
+    }
+    let a = Vec3::dot(r.direction(), r.direction());
+    let b = Vec3::dot(&oc, r.direction());
+    let c = Vec3::dot(&oc, &oc) - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant > 0.0 {
+        let root = discriminant.sqrt();
+        let mut t = (-b - root) / a;
+        if t >= t_max || t <= t_min {
+            t = (-b + root) / a;
+        }
+        if t < t_max && t > t_min {
+            let hit_point = r.point_at_parameter(t);
+            let unit = (hit_point - *center) / radius.abs();
+            let (u, v) = sphere_uv(&unit);
+            let (dpdu, dpdv) = sphere_derivatives(&unit, radius.abs());
+            return Some(
+                HitRecord::new(t, hit_point, (hit_point - *center) / radius, material)
+                    .with_uv(u, v)
+                    .with_derivatives(dpdu, dpdv),
+            );
         }
-        None
+    }
+    None
+}
+
+// Both roots at once, so that rays grazing the sphere still give a proper
+// span.
+fn sphere_intervals<'a>(
+    center: &Vec3,
+    radius: f32,
+    material: &'a dyn Material,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Vec<Interval<'a>> {
+    let oc = *r.origin() - *center;
+    let a = Vec3::dot(r.direction(), r.direction());
+    let b = Vec3::dot(&oc, r.direction());
+    let c = Vec3::dot(&oc, &oc) - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+    let root = discriminant.sqrt();
+    let (t0, t1) = ((-b - root) / a, (-b + root) / a);
+    if t0 <= t_min || t1 >= t_max {
+        return vec![];
+    }
+    let record = |t| {
+        let p = r.point_at_parameter(t);
+        let normal = (p - *center) / radius.abs();
+        let (u, v) = sphere_uv(&normal);
+        let (dpdu, dpdv) = sphere_derivatives(&normal, radius.abs());
+        HitRecord::new(t, p, normal, material)
+            .with_uv(u, v)
+            .with_derivatives(dpdu, dpdv)
+    };
+    vec![Interval {
+        enter: record(t0),
+        exit: record(t1),
+    }]
+}
+
+// Longitude and latitude of a point on the unit sphere: u goes around from
+// -x through +z, and v up from the pole at -y.
+fn sphere_uv(p: &Vec3) -> (f32, f32) {
+    let phi = (-p.z()).atan2(p.x()) + PI;
+    let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+    (phi / (2.0 * PI), theta / PI)
+}
+
+// The derivatives of `sphere_uv`'s parametrisation at a point `p` on the
+// unit sphere, for a sphere of `radius`. dpdv vanishes at the poles.
+fn sphere_derivatives(p: &Vec3, radius: f32) -> (Vec3, Vec3) {
+    let dpdu = 2.0 * PI * radius * Vec3::new(p.z(), 0.0, -p.x());
+    let rho = (p.x() * p.x() + p.z() * p.z()).sqrt();
+    if rho == 0.0 {
+        return (dpdu, Vec3::zero());
+    }
+    let dpdv = PI * radius * Vec3::new(-p.x() * p.y() / rho, rho, -p.y() * p.z() / rho);
+    (dpdu, dpdv)
+}
+
+fn sphere_bounds(center: &Vec3, radius: f32) -> Aabb {
+    let r = Vec3::new(radius.abs(), radius.abs(), radius.abs());
+    Aabb::new(*center - r, *center + r)
+}
+
+// Turns the normals of an object around, e.g. to make the walls of a room
+// face inwards.
+pub struct FlipNormals<H>(pub H);
+
+impl<H: Hitable> Hitable for FlipNormals<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.0.hit(r, t_min, t_max).map(|hit| {
+            let (normal, shading_normal) = (-hit.normal, -hit.shading_normal);
+            hit.with_normal(normal).with_shading_normal(shading_normal)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.0.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Hitable, MovingSphere, Sphere};
+    use crate::{camera::Camera, ray::Ray, sampler::Sampler, test_support::grey, vec3::Vec3};
+    use std::f32::consts::PI;
+
+    #[test]
+    fn test_moving_sphere() {
+        let sphere = MovingSphere::new(
+            Vec3::new(0.0, 0.0, -5.0),
+            Vec3::new(4.0, 0.0, -5.0),
+            0.0,
+            1.0,
+            1.0,
+            grey(),
+        );
+        assert_eq!(Vec3::new(2.0, 0.0, -5.0), sphere.center(0.5));
+        assert_eq!(Vec3::new(4.0, 0.0, -5.0), sphere.center(3.0));
+        let r = Ray::new(Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&r, 0.001, f32::MAX).is_none());
+        let hit = sphere.hit(&r.with_time(0.5), 0.001, f32::MAX).unwrap();
+        assert_eq!(4.0, hit.t());
+        assert_eq!(r.time(), 0.0);
+        let bbox = sphere.bounding_box().unwrap();
+        assert_eq!(
+            (&Vec3::new(-1.0, -1.0, -6.0), &Vec3::new(5.0, 1.0, -4.0)),
+            (bbox.min(), bbox.max())
+        );
+    }
+
+    #[test]
+    fn test_sphere_uv() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 2.0, grey());
+        let hit = |origin, direction| {
+            let hit = sphere
+                .hit(&Ray::new(origin, direction), 0.001, f32::MAX)
+                .unwrap();
+            (hit.u(), hit.v())
+        };
+        // Facing the camera is a quarter of the way around, on the equator.
+        assert_eq!((0.25, 0.5), hit(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0)));
+        let (u, v) = hit(Vec3::new(-10.0, 0.0, -5.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!((u == 0.0 || u == 1.0) && v == 0.5);
+        let (_, v) = hit(Vec3::new(0.0, 10.0, -5.0), Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(1.0, v);
+    }
+
+    #[test]
+    fn test_footprint() {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, -5.0), 2.0, grey());
+        let camera = Camera::new(
+            Vec3::zero(),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let mut sampler = Sampler::new(0);
+        let r = camera.get_ray(0.5, 0.5, &mut sampler);
+        let hit = sphere.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!(r.differentials().is_none());
+        assert!(hit.footprint(&r).is_none());
+
+        // A pixel 0.02 across at distance 1 covers 0.06 at the sphere's
+        // front, which is 4 PI around and 2 PI from pole to pole.
+        let r = camera
+            .with_resolution(100, 100)
+            .get_ray(0.5, 0.5, &mut sampler);
+        let hit = sphere.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((*hit.dpdu() - Vec3::new(4.0 * PI, 0.0, 0.0)).length() < 1e-4);
+        let footprint = hit.footprint(&r).unwrap();
+        assert!((footprint.dudx - 0.06 / (4.0 * PI)).abs() < 1e-6);
+        assert!((footprint.dvdy - 0.06 / (2.0 * PI)).abs() < 1e-6);
+        assert!(footprint.dvdx.abs() < 1e-6 && footprint.dudy.abs() < 1e-6);
     }
 }
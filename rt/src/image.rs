@@ -0,0 +1,422 @@
+use crate::{
+    hdr,
+    tonemap::{srgb_eotf, ToneMap},
+    vec3::Vec3,
+};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// Linear RGB pixels stored row by row from the top of the image. The tone map
+// only applies when writing the 8 bit formats.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+    tone_map: ToneMap,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize) -> Self {
+        Image::from_pixels(width, height, vec![Vec3::zero(); width * height])
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Vec3>) -> Self {
+        assert_eq!(width * height, pixels.len());
+        Image {
+            width,
+            height,
+            pixels,
+            tone_map: ToneMap::default(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Vec3] {
+        &mut self.pixels
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Vec3 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, col: Vec3) {
+        self.pixels[y * self.width + x] = col;
+    }
+
+    pub fn tone_map(&self) -> &ToneMap {
+        &self.tone_map
+    }
+
+    pub fn set_tone_map(&mut self, tone_map: ToneMap) {
+        self.tone_map = tone_map;
+    }
+
+    // Picks the format from the extension: ppm, png, pfm or hdr.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let format = Format::from_path(path)?;
+        let mut out = BufWriter::new(File::create(path)?);
+        match format {
+            Format::Ppm => self.write_ppm(&mut out)?,
+            Format::Png => self.write_png(&mut out)?,
+            Format::Pfm => self.write_pfm(&mut out)?,
+            Format::Hdr => hdr::write_hdr(&mut out, self)?,
+        }
+        out.flush()
+    }
+
+    // Picks the format from the extension like `save`. The 8 bit formats are
+    // taken to be sRGB encoded and come back linear, untouched by any tone
+    // map.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        let format = Format::from_path(path)?;
+        let reader = BufReader::new(File::open(path)?);
+        match format {
+            Format::Ppm => read_ppm(reader),
+            Format::Png => read_png(reader),
+            Format::Pfm => read_pfm(reader),
+            Format::Hdr => hdr::read_hdr(reader),
+        }
+    }
+
+    // Binary P6 with 8 bits per channel.
+    pub fn write_ppm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        out.write_all(&self.to_rgb8())
+    }
+
+    pub fn write_png<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer
+            .write_image_data(&self.to_rgb8())
+            .map_err(io::Error::other)
+    }
+
+    // Little endian floats; PFM stores its rows from the bottom up.
+    pub fn write_pfm<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        if self.width == 0 || self.height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cannot write an empty image",
+            ));
+        }
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        for row in self.pixels.chunks(self.width).rev() {
+            for col in row {
+                for i in 0..3 {
+                    out.write_all(&col[i].to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn to_rgb8(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(3 * self.pixels.len());
+        for col in self.pixels.iter() {
+            bytes.extend_from_slice(&self.tone_map.to_srgb8(col));
+        }
+        bytes
+    }
+}
+
+enum Format {
+    Ppm,
+    Png,
+    Pfm,
+    Hdr,
+}
+
+impl Format {
+    fn from_path(path: &Path) -> io::Result<Self> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("ppm") => Ok(Format::Ppm),
+            Some("png") => Ok(Format::Png),
+            Some("pfm") => Ok(Format::Pfm),
+            Some("hdr") => Ok(Format::Hdr),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown image format: {}", path.display()),
+            )),
+        }
+    }
+}
+
+pub fn read_pfm<R: BufRead>(mut reader: R) -> io::Result<Image> {
+    let mut header = String::new();
+    while header.split_whitespace().count() < 4 {
+        if reader.read_line(&mut header)? == 0 {
+            return Err(invalid("unexpected end of header"));
+        }
+    }
+    let fields: Vec<&str> = header.split_whitespace().collect();
+    let channels = match fields[0] {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("not a PFM file")),
+    };
+    let width = hdr::parse_dim(fields[1])?;
+    let height = hdr::parse_dim(fields[2])?;
+    let scale: f32 = fields[3].parse().map_err(|_| invalid("bad scale"))?;
+
+    let data = read_data(&mut reader, &[4, channels, width, height])?;
+    let values: Vec<f32> = data
+        .chunks(4)
+        .map(|b| {
+            let b = [b[0], b[1], b[2], b[3]];
+            if scale < 0.0 {
+                f32::from_le_bytes(b)
+            } else {
+                f32::from_be_bytes(b)
+            }
+        })
+        .collect();
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in values.chunks(channels * width).rev() {
+        for v in row.chunks(channels) {
+            pixels.push(if channels == 3 {
+                Vec3::new(v[0], v[1], v[2])
+            } else {
+                Vec3::new(v[0], v[0], v[0])
+            });
+        }
+    }
+    Ok(Image::from_pixels(width, height, pixels))
+}
+
+// Binary P6 with up to 16 bits per channel.
+pub fn read_ppm<R: BufRead>(mut reader: R) -> io::Result<Image> {
+    let mut fields = vec![];
+    let mut token = vec![];
+    let mut comment = false;
+    while fields.len() < 4 {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        match byte[0] {
+            b'\n' | b'\r' => comment = false,
+            _ if comment => continue,
+            b'#' => comment = true,
+            b if !b.is_ascii_whitespace() => {
+                token.push(b);
+                continue;
+            }
+            _ => {}
+        }
+        if !token.is_empty() {
+            fields.push(String::from_utf8_lossy(&token).into_owned());
+            token.clear();
+        }
+    }
+    if fields[0] != "P6" {
+        return Err(invalid("not a binary PPM file"));
+    }
+    let width = hdr::parse_dim(&fields[1])?;
+    let height = hdr::parse_dim(&fields[2])?;
+    let max: u32 = fields[3]
+        .parse()
+        .map_err(|_| invalid("bad maximum value"))?;
+    if max == 0 || max > 65535 {
+        return Err(invalid("bad maximum value"));
+    }
+
+    let size = if max > 255 { 2 } else { 1 };
+    let data = read_data(&mut reader, &[size, 3, width, height])?;
+    let values: Vec<f32> = data
+        .chunks(size)
+        .map(|b| {
+            let value = if size == 2 {
+                u16::from_be_bytes([b[0], b[1]]) as u32
+            } else {
+                b[0] as u32
+            };
+            srgb_eotf(value as f32 / max as f32)
+        })
+        .collect();
+    let pixels = values
+        .chunks(3)
+        .map(|v| Vec3::new(v[0], v[1], v[2]))
+        .collect();
+    Ok(Image::from_pixels(width, height, pixels))
+}
+
+// Any color type and depth; alpha is dropped.
+pub fn read_png<R: BufRead>(reader: R) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+    let mut data = vec![0u8; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).map_err(io::Error::other)?;
+    let channels = info.color_type.samples();
+    let decode = |b: u8| srgb_eotf(b as f32 / 255.0);
+    let pixels = data[..info.buffer_size()]
+        .chunks(info.line_size)
+        .flat_map(|row| row[..channels * info.width as usize].chunks(channels))
+        .map(|p| {
+            if channels < 3 {
+                Vec3::new(decode(p[0]), decode(p[0]), decode(p[0]))
+            } else {
+                Vec3::new(decode(p[0]), decode(p[1]), decode(p[2]))
+            }
+        })
+        .collect();
+    Ok(Image::from_pixels(
+        info.width as usize,
+        info.height as usize,
+        pixels,
+    ))
+}
+
+// Reads as many bytes as the product of `sizes`, growing the buffer only as
+// far as the data goes rather than trusting the header.
+fn read_data<R: Read>(reader: R, sizes: &[usize]) -> io::Result<Vec<u8>> {
+    let size = sizes
+        .iter()
+        .try_fold(1usize, |size, &n| size.checked_mul(n))
+        .ok_or_else(|| invalid("image too large"))?;
+    let mut data = vec![];
+    reader.take(size as u64).read_to_end(&mut data)?;
+    if data.len() < size {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "unexpected end of data",
+        ));
+    }
+    Ok(data)
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_pfm, read_png, read_ppm, Image};
+    use crate::{
+        hdr,
+        tonemap::{Operator, ToneMap},
+        vec3::Vec3,
+    };
+
+    fn test_image() -> Image {
+        Image::from_pixels(
+            3,
+            2,
+            vec![
+                Vec3::new(0.0, 0.25, 1.0),
+                Vec3::new(2.0, 0.5, 0.125),
+                Vec3::new(100.0, 0.0, 0.0),
+                Vec3::new(1.0, 1.0, 1.0),
+                Vec3::zero(),
+                Vec3::new(0.001, 0.01, 0.1),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_ppm() {
+        let mut out = vec![];
+        test_image().write_ppm(&mut out).unwrap();
+        assert!(out.starts_with(b"P6\n3 2\n255\n"));
+        assert_eq!(&[0, 137, 255, 255, 188, 99], &out[11..17]);
+        assert_eq!(11 + 18, out.len());
+
+        let mut image = test_image();
+        image.set_tone_map(ToneMap::new(Operator::Reinhard, -1.0));
+        let mut out = vec![];
+        image.write_ppm(&mut out).unwrap();
+        assert_eq!(&[0, 94, 156, 188, 124, 69], &out[11..17]);
+    }
+
+    #[test]
+    fn test_pfm_round_trip() {
+        let mut out = vec![];
+        test_image().write_pfm(&mut out).unwrap();
+        assert_eq!(test_image(), read_pfm(&out[..]).unwrap());
+        assert!(read_pfm(&b"PF\n0 1\n-1.0\n"[..]).is_err());
+        assert!(Image::new(0, 0).write_pfm(&mut vec![]).is_err());
+        assert!(read_pfm(&b"PF\n4000000000 4000000000\n-1.0\n"[..]).is_err());
+        assert!(read_pfm(&b"PF\n18446744073709551615 2\n-1.0\n"[..]).is_err());
+    }
+
+    #[test]
+    fn test_hdr_round_trip() {
+        // Scanlines of eight pixels or more are run length encoded.
+        let wide = Image::from_pixels(
+            20,
+            1,
+            (0..20)
+                .map(|i| Vec3::new((i / 6) as f32, 0.5, i as f32 * 0.25))
+                .collect(),
+        );
+        for expected in [test_image(), wide].iter() {
+            let mut out = vec![];
+            hdr::write_hdr(&mut out, expected).unwrap();
+            let image = hdr::read_hdr(&out[..]).unwrap();
+            assert_eq!(
+                (expected.width(), expected.height()),
+                (image.width(), image.height())
+            );
+            for (a, b) in expected.pixels().iter().zip(image.pixels()) {
+                let max = a.r().max(a.g()).max(a.b());
+                assert!((*a - *b).length() <= max / 128.0, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_png() {
+        let mut out = vec![];
+        test_image().write_png(&mut out).unwrap();
+        assert!(out.starts_with(b"\x89PNG\r\n\x1a\n"));
+    }
+
+    // Reading back what the default tone map wrote loses only what it
+    // clamped and the 8 bit rounding.
+    fn assert_ldr_round_trip(image: &Image) {
+        assert_eq!((3, 2), (image.width(), image.height()));
+        for (a, b) in test_image().pixels().iter().zip(image.pixels()) {
+            for i in 0..3 {
+                assert!((a[i].min(1.0) - b[i]).abs() < 0.01, "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ldr_round_trip() {
+        let mut out = vec![];
+        test_image().write_png(&mut out).unwrap();
+        assert_ldr_round_trip(&read_png(&out[..]).unwrap());
+        let mut out = vec![];
+        test_image().write_ppm(&mut out).unwrap();
+        assert_ldr_round_trip(&read_ppm(&out[..]).unwrap());
+
+        let commented = b"P6 # a comment\n1 1\n# another\n65535\n\xff\xff\x80\x00\x00\x00";
+        let image = read_ppm(&commented[..]).unwrap();
+        assert!((image.pixel(0, 0) - Vec3::new(1.0, 0.214, 0.0)).length() < 1e-3);
+        assert!(read_ppm(&b"P3\n1 1\n255\n0 0 0\n"[..]).is_err());
+        assert!(read_ppm(&b"P6\n0 1\n255\n"[..]).is_err());
+        assert!(read_ppm(&b"P6\n4000000000 4000000000\n255\n"[..]).is_err());
+        assert!(read_ppm(&b"P6\n18446744073709551615 2\n255\n"[..]).is_err());
+    }
+}
@@ -1,5 +1,29 @@
+pub mod aabb;
+pub mod bump;
+pub mod bvh;
 pub mod camera;
+pub mod csg;
+pub mod environment;
+pub mod gltf;
+pub mod hdr;
 pub mod hitable;
+pub mod image;
 pub mod material;
+pub mod mesh;
+pub mod noise;
+pub mod obj;
+pub mod pattern;
+pub mod ply;
+pub mod quadric;
 pub mod ray;
+pub mod rect;
+pub mod render;
+pub mod sampler;
+pub mod scene;
+pub mod sdf;
+#[cfg(test)]
+mod test_support;
+pub mod texture;
+pub mod tonemap;
+pub mod transform;
 pub mod vec3;
@@ -1,35 +1,88 @@
-use crate::{hitable::HitRecord, ray::Ray, vec3::Vec3};
-use rand::{thread_rng, Rng};
+use crate::{
+    hitable::HitRecord,
+    ray::{Differentials, Ray},
+    sampler::Sampler,
+    texture::{SolidColor, Texture},
+    vec3::Vec3,
+};
+use rand::Rng;
+use std::f32::consts::PI;
 
-pub trait Material {
-    fn scatter(&self, r: &Ray, hit: &HitRecord) -> Option<(Vec3, Ray)>;
+pub trait Material: Send + Sync {
+    fn scatter(&self, r: &Ray, hit: &HitRecord, sampler: &mut Sampler) -> Option<(Vec3, Ray)>;
+
+    fn emitted(&self, _r: &Ray, _hit: &HitRecord) -> Vec3 {
+        Vec3::zero()
+    }
+
+    // For light arriving along `wi`, the reflected fraction times the cosine
+    // term, and the pdf with which `scatter` would have picked `wi`. Materials
+    // that only scatter into discrete directions return None, which keeps the
+    // renderer from sampling lights for them.
+    fn eval(&self, _r: &Ray, _hit: &HitRecord, _wi: &Vec3) -> Option<(Vec3, f32)> {
+        None
+    }
 }
 
 pub struct Lambertian {
-    albedo: Vec3,
+    albedo: Box<dyn Texture>,
 }
 
 impl Lambertian {
     pub fn new(albedo: Vec3) -> Self {
+        Lambertian::textured(Box::new(SolidColor::new(albedo)))
+    }
+
+    pub fn textured(albedo: Box<dyn Texture>) -> Self {
         Lambertian { albedo }
     }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, _: &Ray, hit: &HitRecord) -> Option<(Vec3, Ray)> {
-        let target = *hit.p() + *hit.normal() + random_in_unit_sphere();
-        Some((self.albedo, Ray::new(*hit.p(), target - *hit.p())))
+    fn scatter(&self, r: &Ray, hit: &HitRecord, sampler: &mut Sampler) -> Option<(Vec3, Ray)> {
+        let (normal, shading_normal) = facing_normals(r, hit);
+        // A point on the unit sphere around the tip of the normal gives
+        // directions distributed by cos(theta) / PI, as `eval` assumes.
+        let mut direction = shading_normal + random_unit_vector(sampler);
+        if direction.squared_length() < 1e-8 {
+            direction = shading_normal;
+        }
+        // A bent shading normal must not send light through the surface.
+        if Vec3::dot(&direction, &normal) <= 0.0 {
+            return None;
+        }
+        let scattered =
+            Ray::new(offset_origin(hit, &normal, &direction), direction).with_time(r.time());
+        Some((
+            albedo(self.albedo.as_ref(), r, hit) * *hit.color(),
+            scattered,
+        ))
+    }
+
+    fn eval(&self, r: &Ray, hit: &HitRecord, wi: &Vec3) -> Option<(Vec3, f32)> {
+        let (normal, shading_normal) = facing_normals(r, hit);
+        let wi = wi.unit_vector();
+        let cosine = Vec3::dot(&wi, &shading_normal).max(0.0);
+        if Vec3::dot(&wi, &normal) <= 0.0 {
+            return Some((Vec3::zero(), cosine / PI));
+        }
+        let albedo = albedo(self.albedo.as_ref(), r, hit) * *hit.color();
+        Some((albedo * cosine / PI, cosine / PI))
     }
 }
 
 pub struct Metal {
-    albedo: Vec3,
+    albedo: Box<dyn Texture>,
     fuzz: f32,
 }
 
 impl Metal {
     pub fn new(albedo: Vec3, fuzz: f32) -> Self {
-        if fuzz < 0.0 || fuzz > 1.0 {
+        Metal::textured(Box::new(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn textured(albedo: Box<dyn Texture>, fuzz: f32) -> Self {
+        if !(0.0..=1.0).contains(&fuzz) {
             Metal { albedo, fuzz: 1.0 }
         } else {
             Metal { albedo, fuzz }
@@ -38,18 +91,28 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, r: &Ray, hit: &HitRecord) -> Option<(Vec3, Ray)> {
-        let reflected = reflect(&r.direction().unit_vector(), hit.normal());
-        let scattered = if self.fuzz > 0.0 {
-            Ray::new(*hit.p(), reflected + self.fuzz * random_in_unit_sphere())
+    fn scatter(&self, r: &Ray, hit: &HitRecord, sampler: &mut Sampler) -> Option<(Vec3, Ray)> {
+        let (facing, _) = facing_normals(r, hit);
+        let normal = mirror_normal(r.direction(), hit);
+        let reflected = reflect(&r.direction().unit_vector(), &normal);
+        let direction = if self.fuzz > 0.0 {
+            reflected + self.fuzz * random_in_unit_sphere(sampler)
         } else {
-            Ray::new(*hit.p(), reflected)
+            reflected
         };
-        if Vec3::dot(scattered.direction(), hit.normal()) > 0.0 {
-            Some((self.albedo, scattered))
-        } else {
-            None
+        if Vec3::dot(&direction, &facing) <= 0.0 {
+            return None;
         }
+        let scattered =
+            Ray::new(offset_origin(hit, &facing, &direction), direction).with_time(r.time());
+        let scattered = if self.fuzz > 0.0 {
+            scattered
+        } else {
+            with_bent_differentials(scattered, r, hit, |d| {
+                Some(reflect(&d.unit_vector(), &normal))
+            })
+        };
+        Some((albedo(self.albedo.as_ref(), r, hit), scattered))
     }
 }
 
@@ -64,20 +127,20 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r: &Ray, hit: &HitRecord) -> Option<(Vec3, Ray)> {
-        let dt = Vec3::dot(r.direction(), hit.normal());
-        let (outward_normal, ni_over_nt, cosine) = if dt > 0.0 {
+    fn scatter(&self, r: &Ray, hit: &HitRecord, sampler: &mut Sampler) -> Option<(Vec3, Ray)> {
+        // Which side the ray comes from is up to the surface itself; how it
+        // bends is up to the shading normal.
+        let (facing, _) = facing_normals(r, hit);
+        let normal = mirror_normal(r.direction(), hit);
+        let dt = Vec3::dot(r.direction(), &normal);
+        let (outward_normal, ni_over_nt, cosine) = if Vec3::dot(r.direction(), hit.normal()) > 0.0 {
             (
-                -*hit.normal(),
+                -normal,
                 self.ref_idx,
                 self.ref_idx * dt / r.direction().length(),
             )
         } else {
-            (
-                *hit.normal(),
-                1.0 / self.ref_idx,
-                -dt / r.direction().length(),
-            )
+            (normal, 1.0 / self.ref_idx, -dt / r.direction().length())
         };
         let (reflect_prob, refracted) =
             if let Some(refracted) = refract(r.direction(), &outward_normal, ni_over_nt) {
@@ -85,30 +148,130 @@ impl Material for Dielectric {
             } else {
                 (1.0, None)
             };
-        if reflect_prob < 1.0 {
-            let mut rng = thread_rng();
-            if rng.gen::<f32>() > reflect_prob {
-                return Some((
-                    Vec3::new(1.0, 1.0, 1.0),
-                    Ray::new(*hit.p(), refracted.unwrap()),
-                ));
-            }
+        if reflect_prob < 1.0 && sampler.gen::<f32>() > reflect_prob {
+            let refracted = refracted.unwrap();
+            let origin = offset_origin(hit, &facing, &refracted);
+            let scattered = Ray::new(origin, refracted).with_time(r.time());
+            return Some((
+                Vec3::new(1.0, 1.0, 1.0),
+                with_bent_differentials(scattered, r, hit, |d| {
+                    refract(d, &outward_normal, ni_over_nt)
+                }),
+            ));
         }
-        let reflected = reflect(r.direction(), hit.normal());
-        Some((Vec3::new(1.0, 1.0, 1.0), Ray::new(*hit.p(), reflected)))
+        let reflected = reflect(r.direction(), &normal);
+        let origin = offset_origin(hit, &facing, &reflected);
+        let scattered = Ray::new(origin, reflected).with_time(r.time());
+        Some((
+            Vec3::new(1.0, 1.0, 1.0),
+            with_bent_differentials(scattered, r, hit, |d| Some(reflect(d, &normal))),
+        ))
+    }
+}
+
+pub struct DiffuseLight {
+    emit: Vec3,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vec3) -> Self {
+        DiffuseLight { emit }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _: &Ray, _: &HitRecord, _: &mut Sampler) -> Option<(Vec3, Ray)> {
+        None
+    }
+
+    fn emitted(&self, _: &Ray, _: &HitRecord) -> Vec3 {
+        self.emit
     }
 }
 
-fn random_in_unit_sphere() -> Vec3 {
-    let mut rng = thread_rng();
+// Filtered over the pixel's footprint where the ray has differentials.
+fn albedo(texture: &dyn Texture, r: &Ray, hit: &HitRecord) -> Vec3 {
+    match hit.footprint(r) {
+        Some(footprint) => texture.filtered(hit.u(), hit.v(), hit.p(), &footprint),
+        None => texture.value(hit.u(), hit.v(), hit.p()),
+    }
+}
+
+// Gives a ray leaving a mirror or glass surface the differentials of the
+// one arriving: the neighbouring rays start where they cross the tangent
+// plane and are turned by `bend` about the same normal. None from `bend`,
+// e.g. past the critical angle, leaves the ray without.
+fn with_bent_differentials(
+    scattered: Ray,
+    r: &Ray,
+    hit: &HitRecord,
+    bend: impl Fn(&Vec3) -> Option<Vec3>,
+) -> Ray {
+    let bent = || {
+        let d = r.differentials()?;
+        let (rx_origin, ry_origin) = hit.differential_points(r)?;
+        Some(Differentials {
+            rx_origin,
+            rx_direction: bend(&d.rx_direction)?,
+            ry_origin,
+            ry_direction: bend(&d.ry_direction)?,
+        })
+    };
+    match bent() {
+        Some(differentials) => scattered.with_differentials(differentials),
+        None => scattered,
+    }
+}
+
+// Both normals turned to the side of the surface the ray came from.
+fn facing_normals(r: &Ray, hit: &HitRecord) -> (Vec3, Vec3) {
+    if Vec3::dot(r.direction(), hit.normal()) > 0.0 {
+        (-*hit.normal(), -*hit.shading_normal())
+    } else {
+        (*hit.normal(), *hit.shading_normal())
+    }
+}
+
+// Where a ray leaving the surface along `direction` starts: moved a little
+// off it along the geometric `normal`, to whichever side the ray goes, so
+// that it does not hit the same surface again straight away.
+fn offset_origin(hit: &HitRecord, normal: &Vec3, direction: &Vec3) -> Vec3 {
+    let offset = 1e-4 * hit.p().length().max(1.0) * *normal;
+    if Vec3::dot(direction, normal) > 0.0 {
+        *hit.p() + offset
+    } else {
+        *hit.p() - offset
+    }
+}
+
+// The shading normal, unless mirroring `direction` about it would carry the
+// light through the surface, in which case the geometric one.
+fn mirror_normal(direction: &Vec3, hit: &HitRecord) -> Vec3 {
+    let reflected = reflect(direction, hit.shading_normal());
+    if Vec3::dot(&reflected, hit.normal()) * Vec3::dot(direction, hit.normal()) < 0.0 {
+        *hit.shading_normal()
+    } else {
+        *hit.normal()
+    }
+}
+
+fn random_in_unit_sphere(sampler: &mut Sampler) -> Vec3 {
     loop {
-        let p = 2.0 * Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(1.0, 1.0, 1.0);
+        let p =
+            2.0 * Vec3::new(sampler.gen(), sampler.gen(), sampler.gen()) - Vec3::new(1.0, 1.0, 1.0);
         if p.squared_length() < 1.0 {
             break p;
         }
     }
 }
 
+fn random_unit_vector(sampler: &mut Sampler) -> Vec3 {
+    let z: f32 = 2.0 * sampler.gen::<f32>() - 1.0;
+    let phi = 2.0 * PI * sampler.gen::<f32>();
+    let r = (1.0 - z * z).max(0.0).sqrt();
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
 fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
     *v - 2.0 * Vec3::dot(v, n) * *n
 }
@@ -128,3 +291,83 @@ fn schlick(cosine: f32, ref_idx: f32) -> f32 {
     let r0 = ((1.0 - ref_idx) / (1.0 + ref_idx)).powi(2);
     r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Dielectric, Material, Metal};
+    use crate::{
+        hitable::Hitable,
+        quadric::Plane,
+        ray::{Differentials, Ray},
+        rect::XzRect,
+        sampler::Sampler,
+        test_support::grey,
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_mirror_differentials() {
+        let floor =
+            |material: Box<dyn Material>| XzRect::new((-1.0, 1.0), (-1.0, 1.0), 0.0, material);
+        let r = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).with_differentials(
+            Differentials {
+                rx_origin: Vec3::new(0.0, 1.0, 0.0),
+                rx_direction: Vec3::new(0.01, -1.0, 0.0),
+                ry_origin: Vec3::new(0.0, 1.0, 0.0),
+                ry_direction: Vec3::new(0.0, -1.0, 0.01),
+            },
+        );
+        let mut sampler = Sampler::new(0);
+
+        let mirror = floor(Box::new(Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.0)));
+        let hit = mirror.hit(&r, 0.001, f32::MAX).unwrap();
+        let (_, scattered) = hit.material().scatter(&r, &hit, &mut sampler).unwrap();
+        let d = scattered.differentials().unwrap();
+        assert!((d.rx_origin - Vec3::new(0.01, 0.0, 0.0)).length() < 1e-6);
+        assert!((d.rx_direction - Vec3::new(0.01, 1.0, 0.0).unit_vector()).length() < 1e-6);
+        assert!((d.ry_origin - Vec3::new(0.0, 0.0, 0.01)).length() < 1e-6);
+
+        // Diffuse bounces spread too widely for differentials to mean much.
+        let matte = floor(grey());
+        let hit = matte.hit(&r, 0.001, f32::MAX).unwrap();
+        let (_, scattered) = hit.material().scatter(&r, &hit, &mut sampler).unwrap();
+        assert!(scattered.differentials().is_none());
+    }
+
+    #[test]
+    fn test_back_faces() {
+        // Hit from below, against the plane's normal.
+        let up = Vec3::new(0.0, 1.0, 0.0);
+        let r = Ray::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.3, 1.0, 0.0));
+        let mut sampler = Sampler::new(0);
+        let materials: Vec<Box<dyn Material>> =
+            vec![grey(), Box::new(Metal::new(Vec3::new(0.9, 0.9, 0.9), 0.3))];
+        for material in materials {
+            let plane = Plane::new(Vec3::zero(), up, material);
+            let hit = plane.hit(&r, 0.001, f32::MAX).unwrap();
+            for _ in 0..1000 {
+                if let Some((_, scattered)) = hit.material().scatter(&r, &hit, &mut sampler) {
+                    assert!(scattered.direction().y() < 0.0);
+                    assert!(scattered.origin().y() < 0.0);
+                }
+            }
+        }
+
+        let plane = Plane::new(Vec3::zero(), up, grey());
+        let hit = plane.hit(&r, 0.001, f32::MAX).unwrap();
+        let (f, _) = hit.material().eval(&r, &hit, &up).unwrap();
+        assert_eq!(Vec3::zero(), f);
+        let (f, pdf) = hit.material().eval(&r, &hit, &-up).unwrap();
+        assert!(f.x() > 0.0 && pdf > 0.0);
+
+        // Glass refracts to the far side and reflects back, starting off the
+        // surface on those sides.
+        let glass = Plane::new(Vec3::zero(), up, Box::new(Dielectric::new(1.5)));
+        let hit = glass.hit(&r, 0.001, f32::MAX).unwrap();
+        for _ in 0..100 {
+            let (_, scattered) = hit.material().scatter(&r, &hit, &mut sampler).unwrap();
+            let side = scattered.direction().y().signum();
+            assert_eq!(side, scattered.origin().y().signum());
+        }
+    }
+}
@@ -0,0 +1,418 @@
+use crate::{
+    aabb::Aabb,
+    bvh::{BvhStats, BvhTree},
+    hitable::{HitRecord, Hitable},
+    material::Material,
+    ray::Ray,
+    vec3::Vec3,
+};
+
+// Boxes are padded, relative to the size of the coordinates, so that rounding
+// in the slab test cannot miss a triangle that is flat or hit at a corner.
+const BOUNDS_PAD: f32 = 1e-5;
+
+pub struct Triangle {
+    vertices: [Vec3; 3],
+    material: Box<dyn Material>,
+}
+
+impl Triangle {
+    // The normal faces the side from which the vertices appear
+    // counter-clockwise.
+    pub fn new(a: Vec3, b: Vec3, c: Vec3, material: Box<dyn Material>) -> Self {
+        Triangle {
+            vertices: [a, b, c],
+            material,
+        }
+    }
+
+    pub fn vertices(&self) -> &[Vec3; 3] {
+        &self.vertices
+    }
+}
+
+impl Hitable for Triangle {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let [a, b, c] = &self.vertices;
+        let (t, u, v) = intersect(r, a, b, c, t_min, t_max)?;
+        Some(
+            HitRecord::new(
+                t,
+                r.point_at_parameter(t),
+                face_normal(a, b, c),
+                self.material.as_ref(),
+            )
+            .with_uv(u, v)
+            .with_derivatives(*b - *a, *c - *a),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [a, b, c] = &self.vertices;
+        Some(triangle_bounds(a, b, c))
+    }
+}
+
+// Triangles sharing one vertex buffer and one material, with a BVH over them.
+pub struct TriangleMesh {
+    vertices: Vec<Vec3>,
+    normals: Option<Vec<Vec3>>,
+    uvs: Option<Vec<[f32; 2]>>,
+    colors: Option<Vec<Vec3>>,
+    indices: Vec<[u32; 3]>,
+    material: Box<dyn Material>,
+    tree: BvhTree,
+}
+
+impl TriangleMesh {
+    // Panics if an index is out of range.
+    pub fn new(vertices: Vec<Vec3>, indices: Vec<[u32; 3]>, material: Box<dyn Material>) -> Self {
+        let bounds: Vec<Aabb> = indices
+            .iter()
+            .map(|tri| {
+                let [a, b, c] = tri.map(|i| vertices[i as usize]);
+                triangle_bounds(&a, &b, &c)
+            })
+            .collect();
+        TriangleMesh {
+            tree: BvhTree::new(&bounds),
+            vertices,
+            normals: None,
+            uvs: None,
+            colors: None,
+            indices,
+            material,
+        }
+    }
+
+    // Per vertex normals, interpolated across each triangle.
+    pub fn with_normals(mut self, normals: Vec<Vec3>) -> Self {
+        assert_eq!(self.vertices.len(), normals.len());
+        self.normals = Some(normals);
+        self
+    }
+
+    // Per vertex texture coordinates, reported as the hit's u and v in place
+    // of the barycentrics.
+    pub fn with_uvs(mut self, uvs: Vec<[f32; 2]>) -> Self {
+        assert_eq!(self.vertices.len(), uvs.len());
+        self.uvs = Some(uvs);
+        self
+    }
+
+    pub fn with_colors(mut self, colors: Vec<Vec3>) -> Self {
+        assert_eq!(self.vertices.len(), colors.len());
+        self.colors = Some(colors);
+        self
+    }
+
+    pub fn vertices(&self) -> &[Vec3] {
+        &self.vertices
+    }
+
+    pub fn normals(&self) -> Option<&[Vec3]> {
+        self.normals.as_deref()
+    }
+
+    pub fn uvs(&self) -> Option<&[[f32; 2]]> {
+        self.uvs.as_deref()
+    }
+
+    pub fn colors(&self) -> Option<&[Vec3]> {
+        self.colors.as_deref()
+    }
+
+    pub fn indices(&self) -> &[[u32; 3]] {
+        &self.indices
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        self.tree.stats()
+    }
+
+    fn hit_triangle(&self, r: &Ray, index: usize, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let [ia, ib, ic] = self.indices[index].map(|i| i as usize);
+        let (a, b, c) = (&self.vertices[ia], &self.vertices[ib], &self.vertices[ic]);
+        let (t, u, v) = intersect(r, a, b, c, t_min, t_max)?;
+        let w = 1.0 - u - v;
+        let mut hit = HitRecord::new(
+            t,
+            r.point_at_parameter(t),
+            face_normal(a, b, c),
+            self.material.as_ref(),
+        );
+        if let Some(normals) = &self.normals {
+            let shading_normal =
+                (w * normals[ia] + u * normals[ib] + v * normals[ic]).unit_vector();
+            // Keep the face on the side the vertex normals point to, whatever
+            // the winding.
+            if Vec3::dot(hit.normal(), &shading_normal) < 0.0 {
+                let normal = -*hit.normal();
+                hit = hit.with_normal(normal);
+            }
+            hit = hit.with_shading_normal(shading_normal);
+        }
+        hit = match &self.uvs {
+            Some(uvs) => {
+                let (dpdu, dpdv) = uv_derivatives(a, b, c, [uvs[ia], uvs[ib], uvs[ic]]);
+                hit.with_uv(
+                    w * uvs[ia][0] + u * uvs[ib][0] + v * uvs[ic][0],
+                    w * uvs[ia][1] + u * uvs[ib][1] + v * uvs[ic][1],
+                )
+                .with_derivatives(dpdu, dpdv)
+            }
+            None => hit.with_uv(u, v).with_derivatives(*b - *a, *c - *a),
+        };
+        if let Some(colors) = &self.colors {
+            hit = hit.with_color(w * colors[ia] + u * colors[ib] + v * colors[ic]);
+        }
+        Some(hit)
+    }
+}
+
+impl Hitable for TriangleMesh {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        self.tree.hit(r, t_min, t_max, |i, closest| {
+            self.hit_triangle(r, i, t_min, closest)
+        })
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.tree.bounding_box()
+    }
+}
+
+fn face_normal(a: &Vec3, b: &Vec3, c: &Vec3) -> Vec3 {
+    Vec3::cross(&(*b - *a), &(*c - *a)).unit_vector()
+}
+
+// How a point on the triangle moves with its texture coordinates. Zero when
+// the coordinates do not span an area.
+fn uv_derivatives(a: &Vec3, b: &Vec3, c: &Vec3, uv: [[f32; 2]; 3]) -> (Vec3, Vec3) {
+    let (du1, dv1) = (uv[1][0] - uv[0][0], uv[1][1] - uv[0][1]);
+    let (du2, dv2) = (uv[2][0] - uv[0][0], uv[2][1] - uv[0][1]);
+    let det = du1 * dv2 - du2 * dv1;
+    if det.abs() < 1e-12 {
+        return (Vec3::zero(), Vec3::zero());
+    }
+    let (e1, e2) = (*b - *a, *c - *a);
+    ((dv2 * e1 - dv1 * e2) / det, (du1 * e2 - du2 * e1) / det)
+}
+
+fn triangle_bounds(a: &Vec3, b: &Vec3, c: &Vec3) -> Aabb {
+    let mut min = *a;
+    let mut max = *a;
+    let mut scale = 1.0f32;
+    for v in [a, b, c].iter() {
+        for i in 0..3 {
+            min[i] = min[i].min(v[i]);
+            max[i] = max[i].max(v[i]);
+            scale = scale.max(v[i].abs());
+        }
+    }
+    Aabb::new(min, max).padded(BOUNDS_PAD * scale)
+}
+
+// Woop, Benthin and Wald, "Watertight Ray/Triangle Intersection" (2013). The
+// vertices are moved into a space where the ray runs along +z from the origin,
+// so that rays through a shared edge or vertex hit at least one of the
+// triangles around it. Returns t and the barycentrics of `b` and `c`.
+fn intersect(
+    r: &Ray,
+    a: &Vec3,
+    b: &Vec3,
+    c: &Vec3,
+    t_min: f32,
+    t_max: f32,
+) -> Option<(f32, f32, f32)> {
+    let dir = r.direction();
+    let kz = if dir.x().abs() > dir.y().abs() && dir.x().abs() > dir.z().abs() {
+        0
+    } else if dir.y().abs() > dir.z().abs() {
+        1
+    } else {
+        2
+    };
+    let (mut kx, mut ky) = ((kz + 1) % 3, (kz + 2) % 3);
+    // Keep the winding, and so the sign of the edge functions, unchanged.
+    if dir[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+    let sx = dir[kx] / dir[kz];
+    let sy = dir[ky] / dir[kz];
+    let sz = 1.0 / dir[kz];
+
+    let a = *a - *r.origin();
+    let b = *b - *r.origin();
+    let c = *c - *r.origin();
+    let (ax, ay) = (a[kx] - sx * a[kz], a[ky] - sy * a[kz]);
+    let (bx, by) = (b[kx] - sx * b[kz], b[ky] - sy * b[kz]);
+    let (cx, cy) = (c[kx] - sx * c[kz], c[ky] - sy * c[kz]);
+
+    let mut u = cx * by - cy * bx;
+    let mut v = ax * cy - ay * cx;
+    let mut w = bx * ay - by * ax;
+    // Exactly on an edge in single precision: decide it in double precision.
+    if u == 0.0 || v == 0.0 || w == 0.0 {
+        let (ax, ay, bx, by, cx, cy) = (
+            ax as f64, ay as f64, bx as f64, by as f64, cx as f64, cy as f64,
+        );
+        u = (cx * by - cy * bx) as f32;
+        v = (ax * cy - ay * cx) as f32;
+        w = (bx * ay - by * ax) as f32;
+    }
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+    let t = (u * sz * a[kz] + v * sz * b[kz] + w * sz * c[kz]) / det;
+    if t <= t_min || t >= t_max {
+        return None;
+    }
+    Some((t, v / det, w / det))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Triangle, TriangleMesh};
+    use crate::{
+        hitable::{Hitable, HitableList},
+        ray::Ray,
+        sampler::Sampler,
+        test_support::grey,
+        vec3::Vec3,
+    };
+    use rand::Rng;
+
+    // An n by n grid of quads in the z = 0 plane spanning [0, 1] x [0, 1].
+    fn grid(n: u32) -> (Vec<Vec3>, Vec<[u32; 3]>) {
+        let mut vertices = vec![];
+        for j in 0..=n {
+            for i in 0..=n {
+                vertices.push(Vec3::new(i as f32 / n as f32, j as f32 / n as f32, 0.0));
+            }
+        }
+        let mut indices = vec![];
+        for j in 0..n {
+            for i in 0..n {
+                let v = j * (n + 1) + i;
+                indices.push([v, v + 1, v + n + 2]);
+                indices.push([v, v + n + 2, v + n + 1]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    #[test]
+    fn test_triangle_hit() {
+        let triangle = Triangle::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, -1.0),
+            grey(),
+        );
+        let r = Ray::new(Vec3::new(0.25, 0.5, 0.0), Vec3::new(0.0, 0.0, -2.0));
+        let hit = triangle.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(0.5, hit.t());
+        assert_eq!(&Vec3::new(0.25, 0.5, -1.0), hit.p());
+        assert_eq!(&Vec3::new(0.0, 0.0, 1.0), hit.normal());
+        assert_eq!((0.25, 0.5), (hit.u(), hit.v()));
+
+        // Seen from behind, with the normal still on the front side.
+        let r = Ray::new(Vec3::new(0.25, 0.25, -2.0), Vec3::new(0.0, 0.0, 1.0));
+        let hit = triangle.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(1.0, hit.t());
+        assert_eq!(&Vec3::new(0.0, 0.0, 1.0), hit.normal());
+
+        let miss = Ray::new(Vec3::new(0.75, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(triangle.hit(&miss, 0.001, f32::MAX).is_none());
+        assert!(triangle.hit(&r, 0.001, 0.5).is_none());
+        assert!(triangle.bounding_box().unwrap().hit(&r, 0.001, f32::MAX));
+    }
+
+    #[test]
+    fn test_mesh_is_watertight() {
+        let (vertices, indices) = grid(7);
+        let mesh = TriangleMesh::new(vertices, indices, grey());
+        assert_eq!(98, mesh.len());
+        // Rays through the shared inner edges and vertices must not slip
+        // through.
+        let mut rng = Sampler::new(5);
+        for _ in 0..20 {
+            for j in 1..7 {
+                for i in 1..7 {
+                    let target = Vec3::new(i as f32 / 7.0, j as f32 / 7.0, 0.0);
+                    let origin = Vec3::new(rng.gen(), rng.gen(), 1.0 + rng.gen::<f32>());
+                    let r = Ray::new(origin, target - origin);
+                    assert!(mesh.hit(&r, 0.001, f32::MAX).is_some(), "{:?}", target);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_mesh_matches_triangles() {
+        let mut rng = Sampler::new(6);
+        let mut vertices = vec![];
+        let mut indices = vec![];
+        let mut list = HitableList::new();
+        for i in 0..300 {
+            let center = Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 10.0;
+            let mut corners = [Vec3::zero(); 3];
+            for corner in corners.iter_mut() {
+                *corner = center + Vec3::new(rng.gen(), rng.gen(), rng.gen());
+                vertices.push(*corner);
+            }
+            indices.push([3 * i, 3 * i + 1, 3 * i + 2]);
+            list.push(Box::new(Triangle::new(
+                corners[0],
+                corners[1],
+                corners[2],
+                grey(),
+            )));
+        }
+        let mesh = TriangleMesh::new(vertices, indices, grey());
+        for _ in 0..1000 {
+            let origin =
+                Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 14.0 - Vec3::new(2.0, 2.0, 2.0);
+            let direction = Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(0.5, 0.5, 0.5);
+            let r = Ray::new(origin, direction);
+            let expected = list
+                .hit(&r, 0.001, f32::MAX)
+                .map(|hit| (hit.t(), hit.u(), hit.v()));
+            let actual = mesh
+                .hit(&r, 0.001, f32::MAX)
+                .map(|hit| (hit.t(), hit.u(), hit.v()));
+            assert_eq!(expected, actual);
+        }
+    }
+
+    #[test]
+    fn test_interpolated_normals() {
+        let (vertices, indices) = grid(1);
+        let normals = vertices
+            .iter()
+            .map(|v| Vec3::new(v.x() - 0.5, 0.0, 1.0).unit_vector())
+            .collect();
+        let mesh = TriangleMesh::new(vertices, indices, grey()).with_normals(normals);
+        let r = Ray::new(Vec3::new(0.5, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = mesh.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((hit.shading_normal().x()).abs() < 1e-6);
+        assert!((hit.shading_normal().length() - 1.0).abs() < 1e-6);
+        let r = Ray::new(Vec3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = mesh.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!(hit.shading_normal().x() < 0.0);
+        assert_eq!(&Vec3::new(0.0, 0.0, 1.0), hit.normal());
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{sampler::Sampler, texture::Texture, vec3::Vec3};
+use rand::{seq::SliceRandom, Rng};
+use std::f32::consts::PI;
+
+const SIZE: usize = 256;
+
+// Gradient noise on the integer lattice, roughly within [-1, 1] and zero at
+// every lattice point. The lattice is built from `seed`, so the same seed
+// always gives the same noise.
+#[derive(Clone)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    permutation: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut sampler = Sampler::new(seed);
+        let gradients = (0..SIZE)
+            .map(|_| {
+                let z: f32 = 2.0 * sampler.gen::<f32>() - 1.0;
+                let phi = 2.0 * PI * sampler.gen::<f32>();
+                let r = (1.0 - z * z).sqrt();
+                Vec3::new(r * phi.cos(), r * phi.sin(), z)
+            })
+            .collect();
+        let mut permutation: Vec<usize> = (0..SIZE).collect();
+        permutation.shuffle(&mut sampler);
+        Perlin {
+            gradients,
+            permutation,
+        }
+    }
+
+    pub fn noise(&self, p: &Vec3) -> f32 {
+        let cell = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let f = [p.x() - cell[0], p.y() - cell[1], p.z() - cell[2]];
+        let i = cell.map(|c| (c as i64).rem_euclid(SIZE as i64) as usize);
+        // Quintic fade, so the noise has a continuous second derivative.
+        let w = f.map(|t| t * t * t * (t * (t * 6.0 - 15.0) + 10.0));
+
+        let mut sum = 0.0;
+        for corner in 0..8 {
+            let d = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let gradient = &self.gradients[self.hash(
+                (i[0] + d[0]) % SIZE,
+                (i[1] + d[1]) % SIZE,
+                (i[2] + d[2]) % SIZE,
+            )];
+            let offset = Vec3::new(f[0] - d[0] as f32, f[1] - d[1] as f32, f[2] - d[2] as f32);
+            let mut weight = 1.0;
+            for axis in 0..3 {
+                weight *= if d[axis] == 1 { w[axis] } else { 1.0 - w[axis] };
+            }
+            sum += weight * Vec3::dot(gradient, &offset);
+        }
+        sum
+    }
+
+    // Fractional Brownian motion: octaves of noise, each at twice the
+    // frequency and half the amplitude of the last.
+    pub fn fbm(&self, p: &Vec3, octaves: usize) -> f32 {
+        self.octaves(p, octaves, |n| n)
+    }
+
+    // The same with the absolute value of each octave, which gives creases
+    // where the noise crosses zero.
+    pub fn turbulence(&self, p: &Vec3, octaves: usize) -> f32 {
+        self.octaves(p, octaves, f32::abs)
+    }
+
+    fn octaves(&self, p: &Vec3, octaves: usize, f: impl Fn(f32) -> f32) -> f32 {
+        let mut sum = 0.0;
+        let mut p = *p;
+        let mut amplitude = 1.0;
+        for _ in 0..octaves {
+            sum += amplitude * f(self.noise(&p));
+            amplitude *= 0.5;
+            p *= 2.0;
+        }
+        sum
+    }
+
+    fn hash(&self, x: usize, y: usize, z: usize) -> usize {
+        let p = &self.permutation;
+        p[(p[(p[x] + y) % SIZE] + z) % SIZE]
+    }
+}
+
+const OCTAVES: usize = 7;
+
+fn mix(a: &Vec3, b: &Vec3, t: f32) -> Vec3 {
+    (1.0 - t) * *a + t * *b
+}
+
+// Veins of `vein` running through `base` across the z axis, bent by
+// turbulence. `scale` is the number of veins per unit.
+pub struct Marble {
+    noise: Perlin,
+    scale: f32,
+    base: Vec3,
+    vein: Vec3,
+}
+
+impl Marble {
+    pub fn new(seed: u64, scale: f32) -> Self {
+        Marble {
+            noise: Perlin::new(seed),
+            scale,
+            base: Vec3::new(0.9, 0.9, 0.88),
+            vein: Vec3::new(0.25, 0.25, 0.3),
+        }
+    }
+
+    pub fn with_colors(mut self, base: Vec3, vein: Vec3) -> Self {
+        self.base = base;
+        self.vein = vein;
+        self
+    }
+}
+
+impl Texture for Marble {
+    fn value(&self, _: f32, _: f32, p: &Vec3) -> Vec3 {
+        let phase = PI * self.scale * p.z() + 6.0 * self.noise.turbulence(p, OCTAVES);
+        let t = (1.0 - phase.sin().abs()).powi(4);
+        mix(&self.base, &self.vein, t)
+    }
+}
+
+// Growth rings around the y axis, `scale` of them per unit, wobbled by
+// noise.
+pub struct Wood {
+    noise: Perlin,
+    scale: f32,
+    light: Vec3,
+    dark: Vec3,
+}
+
+impl Wood {
+    pub fn new(seed: u64, scale: f32) -> Self {
+        Wood {
+            noise: Perlin::new(seed),
+            scale,
+            light: Vec3::new(0.65, 0.45, 0.25),
+            dark: Vec3::new(0.35, 0.2, 0.1),
+        }
+    }
+
+    pub fn with_colors(mut self, light: Vec3, dark: Vec3) -> Self {
+        self.light = light;
+        self.dark = dark;
+        self
+    }
+}
+
+impl Texture for Wood {
+    fn value(&self, _: f32, _: f32, p: &Vec3) -> Vec3 {
+        let radius = (p.x() * p.x() + p.z() * p.z()).sqrt();
+        let grain = self.noise.fbm(&Vec3::new(p.x(), 0.1 * p.y(), p.z()), 3);
+        let ring = (self.scale * radius + 0.5 * grain).rem_euclid(1.0);
+        // Light early wood fading into a thin dark band.
+        mix(&self.light, &self.dark, ring.powi(3))
+    }
+}
+
+// White clouds on a blue sky, `scale` of them per unit, thinning out where
+// the fractal noise is low.
+pub struct Clouds {
+    noise: Perlin,
+    scale: f32,
+    sky: Vec3,
+    cloud: Vec3,
+}
+
+impl Clouds {
+    pub fn new(seed: u64, scale: f32) -> Self {
+        Clouds {
+            noise: Perlin::new(seed),
+            scale,
+            sky: Vec3::new(0.3, 0.5, 0.9),
+            cloud: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn with_colors(mut self, sky: Vec3, cloud: Vec3) -> Self {
+        self.sky = sky;
+        self.cloud = cloud;
+        self
+    }
+}
+
+impl Texture for Clouds {
+    fn value(&self, _: f32, _: f32, p: &Vec3) -> Vec3 {
+        let density = self.noise.fbm(&(self.scale * *p), OCTAVES);
+        mix(&self.sky, &self.cloud, (2.0 * density).clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Clouds, Marble, Perlin, Wood};
+    use crate::{sampler::Sampler, texture::Texture, vec3::Vec3};
+    use rand::Rng;
+
+    fn points() -> Vec<Vec3> {
+        let mut rng = Sampler::new(3);
+        (0..1000)
+            .map(|_| {
+                Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 20.0 - Vec3::new(10.0, 10.0, 10.0)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_perlin() {
+        let noise = Perlin::new(1);
+        assert_eq!(0.0, noise.noise(&Vec3::new(3.0, -2.0, 7.0)));
+        let values: Vec<f32> = points().iter().map(|p| noise.noise(p)).collect();
+        assert!(values.iter().all(|n| n.abs() <= 1.0));
+        assert!(values.iter().any(|n| n.abs() > 0.3));
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        assert!(mean.abs() < 0.05, "{}", mean);
+
+        // Continuous across cell boundaries.
+        let p = Vec3::new(1.0, 0.3, 0.6);
+        let e = Vec3::new(1e-4, 0.0, 0.0);
+        assert!((noise.noise(&(p - e)) - noise.noise(&(p + e))).abs() < 1e-3);
+
+        // The same seed repeats; another does not.
+        let p = Vec3::new(0.3, 1.7, -4.2);
+        assert_eq!(noise.noise(&p), Perlin::new(1).noise(&p));
+        assert_ne!(noise.noise(&p), Perlin::new(2).noise(&p));
+        let shifted = noise.noise(&(p + Vec3::new(256.0, 0.0, 0.0)));
+        assert!((noise.noise(&p) - shifted).abs() < 1e-4);
+
+        assert!(noise.turbulence(&p, 5) >= 0.0);
+        assert_eq!(noise.noise(&p), noise.fbm(&p, 1));
+    }
+
+    #[test]
+    fn test_textures() {
+        let textures: Vec<Box<dyn Texture>> = vec![
+            Box::new(Marble::new(7, 4.0)),
+            Box::new(Wood::new(7, 8.0)),
+            Box::new(Clouds::new(7, 1.0)),
+        ];
+        for texture in textures.iter() {
+            let colors: Vec<Vec3> = points()
+                .iter()
+                .map(|p| texture.value(0.0, 0.0, p))
+                .collect();
+            assert!(colors
+                .iter()
+                .all(|c| (0..3).all(|i| (0.0..=1.0).contains(&c[i]))));
+            // Not a flat color.
+            assert!(colors.iter().any(|c| (*c - colors[0]).length() > 0.1));
+        }
+        let p = Vec3::new(0.5, 0.5, 0.5);
+        assert_eq!(
+            Marble::new(7, 4.0).value(0.0, 0.0, &p),
+            Marble::new(7, 4.0).value(0.0, 0.0, &p)
+        );
+    }
+}
@@ -0,0 +1,487 @@
+use crate::{
+    hitable::HitableList,
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    mesh::TriangleMesh,
+    vec3::Vec3,
+};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, io::Error),
+    Parse { line: usize, message: String },
+    IndexOutOfRange { line: usize, index: i64 },
+    UnknownMaterial { line: usize, name: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ObjError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            ObjError::IndexOutOfRange { line, index } => {
+                write!(f, "line {}: index {} out of range", line, index)
+            }
+            ObjError::UnknownMaterial { line, name } => {
+                write!(f, "line {}: unknown material {}", line, name)
+            }
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io(_, err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// The subset of an MTL material that maps onto our materials.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MtlMaterial {
+    pub diffuse: Vec3,
+    pub specular: Vec3,
+    pub emission: Vec3,
+    pub shininess: f32,
+    pub ior: f32,
+    pub dissolve: f32,
+    pub illum: u32,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        MtlMaterial {
+            diffuse: Vec3::new(0.5, 0.5, 0.5),
+            specular: Vec3::zero(),
+            emission: Vec3::zero(),
+            shininess: 0.0,
+            ior: 1.5,
+            dissolve: 1.0,
+            illum: 1,
+        }
+    }
+}
+
+impl MtlMaterial {
+    // Emissive materials become lights, transparent ones glass, those with
+    // ray traced reflection (illum 3) metal and everything else diffuse.
+    pub fn to_material(&self) -> Box<dyn Material> {
+        if self.emission != Vec3::zero() {
+            Box::new(DiffuseLight::new(self.emission))
+        } else if self.dissolve < 1.0 || [4, 6, 7, 9].contains(&self.illum) {
+            Box::new(Dielectric::new(self.ior))
+        } else if self.illum == 3 {
+            // Blinn-Phong exponent to a roughness.
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt();
+            Box::new(Metal::new(self.specular, fuzz))
+        } else {
+            Box::new(Lambertian::new(self.diffuse))
+        }
+    }
+}
+
+// Loads the meshes of an OBJ file, one per material, with the materials from
+// the MTL libraries it references.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<HitableList, ObjError> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|err| ObjError::Io(path.to_path_buf(), err))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    read_obj(BufReader::new(file), |name| {
+        let path = dir.join(name);
+        let file = File::open(&path).map_err(|err| ObjError::Io(path.clone(), err))?;
+        read_mtl(BufReader::new(file)).map_err(|err| with_path(err, &path))
+    })
+    .map_err(|err| with_path(err, path))
+}
+
+// Read errors from `read_obj` and `read_mtl` come without a path.
+fn with_path(err: ObjError, path: &Path) -> ObjError {
+    match err {
+        ObjError::Io(empty, err) if empty.as_os_str().is_empty() => {
+            ObjError::Io(path.to_path_buf(), err)
+        }
+        err => err,
+    }
+}
+
+// `load_mtl` is called with the name of every `mtllib`.
+pub fn read_obj<R, F>(reader: R, mut load_mtl: F) -> Result<HitableList, ObjError>
+where
+    R: BufRead,
+    F: FnMut(&str) -> Result<HashMap<String, MtlMaterial>, ObjError>,
+{
+    let mut positions = vec![];
+    let mut normals = vec![];
+    let mut uvs = vec![];
+    let mut materials = HashMap::new();
+    let mut groups: Vec<Group> = vec![Group::new(None)];
+    for (number, line) in reader.lines().enumerate() {
+        let number = number + 1;
+        let line = line.map_err(|err| ObjError::Io(PathBuf::new(), err))?;
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("v") => positions.push(parse_vec3(&mut fields, number)?),
+            Some("vn") => normals.push(parse_vec3(&mut fields, number)?),
+            Some("vt") => {
+                let u = parse_f32(fields.next(), number)?;
+                let v = fields
+                    .next()
+                    .map_or(Ok(0.0), |v| parse_f32(Some(v), number))?;
+                uvs.push([u, v]);
+            }
+            Some("f") => {
+                let mut corners = vec![];
+                for corner in fields {
+                    corners.push(parse_corner(
+                        corner,
+                        [positions.len(), uvs.len(), normals.len()],
+                        number,
+                    )?);
+                }
+                if corners.len() < 3 {
+                    return Err(parse_error(number, "face with fewer than three vertices"));
+                }
+                let group = groups.last_mut().unwrap();
+                for i in 1..corners.len() - 1 {
+                    group.faces.push([corners[0], corners[i], corners[i + 1]]);
+                }
+            }
+            Some("usemtl") => {
+                let name = rest_of_line(&line, number)?;
+                if !materials.contains_key(name) {
+                    return Err(ObjError::UnknownMaterial {
+                        line: number,
+                        name: name.to_string(),
+                    });
+                }
+                match groups
+                    .iter()
+                    .position(|g| g.material.as_deref() == Some(name))
+                {
+                    // Keep the current group last so that faces go to it.
+                    Some(i) => {
+                        let group = groups.remove(i);
+                        groups.push(group);
+                    }
+                    None => groups.push(Group::new(Some(name.to_string()))),
+                }
+            }
+            Some("mtllib") => {
+                for name in fields {
+                    materials.extend(load_mtl(name)?);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut list = HitableList::new();
+    for group in groups.iter().filter(|g| !g.faces.is_empty()) {
+        let material = match &group.material {
+            Some(name) => materials[name].to_material(),
+            None => MtlMaterial::default().to_material(),
+        };
+        list.push(Box::new(
+            group.to_mesh(&positions, &uvs, &normals, material),
+        ));
+    }
+    Ok(list)
+}
+
+pub fn read_mtl<R: BufRead>(reader: R) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+    for (number, line) in reader.lines().enumerate() {
+        let number = number + 1;
+        let line = line.map_err(|err| ObjError::Io(PathBuf::new(), err))?;
+        let mut fields = line.split_whitespace();
+        let keyword = match fields.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            let name = rest_of_line(&line, number)?.to_string();
+            if let Some((name, material)) = current.replace((name, MtlMaterial::default())) {
+                materials.insert(name, material);
+            }
+            continue;
+        }
+        let material = match current.as_mut() {
+            Some((_, material)) => material,
+            None if keyword.starts_with('#') => continue,
+            None => return Err(parse_error(number, "statement before newmtl")),
+        };
+        match keyword {
+            "Kd" => material.diffuse = parse_vec3(&mut fields, number)?,
+            "Ks" => material.specular = parse_vec3(&mut fields, number)?,
+            "Ke" => material.emission = parse_vec3(&mut fields, number)?,
+            "Ns" => material.shininess = parse_f32(fields.next(), number)?,
+            "Ni" => material.ior = parse_f32(fields.next(), number)?,
+            "d" => material.dissolve = parse_f32(fields.next(), number)?,
+            "Tr" => material.dissolve = 1.0 - parse_f32(fields.next(), number)?,
+            "illum" => {
+                material.illum = fields
+                    .next()
+                    .and_then(|f| f.parse().ok())
+                    .ok_or_else(|| parse_error(number, "bad illumination model"))?
+            }
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current {
+        materials.insert(name, material);
+    }
+    Ok(materials)
+}
+
+// Position, texture coordinate and normal indices of a face corner.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+struct Group {
+    material: Option<String>,
+    faces: Vec<[Corner; 3]>,
+}
+
+impl Group {
+    fn new(material: Option<String>) -> Self {
+        Group {
+            material,
+            faces: vec![],
+        }
+    }
+
+    // Corners that share all their indices become one mesh vertex. Texture
+    // coordinates and normals are only kept if every corner has them.
+    fn to_mesh(
+        &self,
+        positions: &[Vec3],
+        uvs: &[[f32; 2]],
+        normals: &[Vec3],
+        material: Box<dyn Material>,
+    ) -> TriangleMesh {
+        let corners = self.faces.iter().flatten();
+        let has_uvs = corners.clone().all(|c| c.1.is_some());
+        let has_normals = corners.clone().all(|c| c.2.is_some());
+        let mut vertex_of = HashMap::new();
+        let mut vertices = vec![];
+        let mut mesh_uvs = vec![];
+        let mut mesh_normals = vec![];
+        let mut indices = Vec::with_capacity(self.faces.len());
+        for face in self.faces.iter() {
+            let mut triangle = [0u32; 3];
+            for (index, corner) in triangle.iter_mut().zip(face.iter()) {
+                *index = *vertex_of.entry(*corner).or_insert_with(|| {
+                    vertices.push(positions[corner.0]);
+                    if has_uvs {
+                        mesh_uvs.push(uvs[corner.1.unwrap()]);
+                    }
+                    if has_normals {
+                        mesh_normals.push(normals[corner.2.unwrap()]);
+                    }
+                    vertices.len() as u32 - 1
+                });
+            }
+            indices.push(triangle);
+        }
+        let mut mesh = TriangleMesh::new(vertices, indices, material);
+        if has_uvs {
+            mesh = mesh.with_uvs(mesh_uvs);
+        }
+        if has_normals {
+            mesh = mesh.with_normals(mesh_normals);
+        }
+        mesh
+    }
+}
+
+// `v`, `v/vt`, `v//vn` or `v/vt/vn`, with negative indices counting back from
+// the last element read so far.
+fn parse_corner(corner: &str, counts: [usize; 3], line: usize) -> Result<Corner, ObjError> {
+    let mut indices = [None; 3];
+    for (i, field) in corner.split('/').enumerate() {
+        if i >= 3 {
+            return Err(parse_error(line, "too many indices in face vertex"));
+        }
+        if field.is_empty() {
+            continue;
+        }
+        let index: i64 = field
+            .parse()
+            .map_err(|_| parse_error(line, &format!("bad index {}", field)))?;
+        let resolved = if index < 0 {
+            counts[i] as i64 + index
+        } else {
+            index - 1
+        };
+        if resolved < 0 || resolved >= counts[i] as i64 {
+            return Err(ObjError::IndexOutOfRange { line, index });
+        }
+        indices[i] = Some(resolved as usize);
+    }
+    match indices[0] {
+        Some(position) => Ok((position, indices[1], indices[2])),
+        None => Err(parse_error(line, "face vertex without a position")),
+    }
+}
+
+fn parse_vec3<'a, I: Iterator<Item = &'a str>>(
+    fields: &mut I,
+    line: usize,
+) -> Result<Vec3, ObjError> {
+    Ok(Vec3::new(
+        parse_f32(fields.next(), line)?,
+        parse_f32(fields.next(), line)?,
+        parse_f32(fields.next(), line)?,
+    ))
+}
+
+fn parse_f32(field: Option<&str>, line: usize) -> Result<f32, ObjError> {
+    match field {
+        Some(field) => field
+            .parse()
+            .map_err(|_| parse_error(line, &format!("bad number {}", field))),
+        None => Err(parse_error(line, "missing number")),
+    }
+}
+
+// Names may contain spaces.
+fn rest_of_line(line: &str, number: usize) -> Result<&str, ObjError> {
+    let name = line
+        .trim()
+        .split_once(char::is_whitespace)
+        .map_or("", |(_, rest)| rest.trim());
+    if name.is_empty() {
+        Err(parse_error(number, "missing name"))
+    } else {
+        Ok(name)
+    }
+}
+
+fn parse_error(line: usize, message: &str) -> ObjError {
+    ObjError::Parse {
+        line,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_obj, read_mtl, read_obj, MtlMaterial, ObjError};
+    use crate::{hitable::Hitable, ray::Ray, vec3::Vec3};
+    use std::collections::HashMap;
+    use std::fs;
+
+    const MTL: &str = "# two materials
+newmtl red paint
+Kd 0.8 0.1 0.1
+illum 2
+
+newmtl lamp
+Ke 4 4 4
+";
+
+    // A unit quad at z = 0 in red and a triangle at z = -1 as a light.
+    const OBJ: &str = "mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+usemtl red paint
+f 1/1/1 2/2/1 3/3/1 4/4/1
+usemtl lamp
+v 0 0 -1
+v 1 0 -1
+v 0 1 -1
+f -3 -2 -1
+";
+
+    fn no_mtl(name: &str) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+        panic!("unexpected mtllib {}", name)
+    }
+
+    #[test]
+    fn test_read_mtl() {
+        let materials = read_mtl(MTL.as_bytes()).unwrap();
+        assert_eq!(2, materials.len());
+        let red = &materials["red paint"];
+        assert_eq!(Vec3::new(0.8, 0.1, 0.1), red.diffuse);
+        assert_eq!(2, red.illum);
+        assert_eq!(Vec3::new(4.0, 4.0, 4.0), materials["lamp"].emission);
+        assert!(read_mtl("Kd 1 1 1\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_read_obj() {
+        let list = read_obj(OBJ.as_bytes(), |name| {
+            assert_eq!("scene.mtl", name);
+            read_mtl(MTL.as_bytes())
+        })
+        .unwrap();
+        assert_eq!(2, list.len());
+
+        let r = Ray::new(Vec3::new(0.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = list.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(1.0, hit.t());
+        assert_eq!(&Vec3::new(0.0, 0.0, 1.0), hit.normal());
+        assert!((hit.u() - 0.75).abs() < 1e-6 && (hit.v() - 0.25).abs() < 1e-6);
+        assert_eq!(Vec3::zero(), hit.material().emitted(&r, &hit));
+
+        let r = Ray::new(Vec3::new(0.1, 0.1, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = list.hit(&r, 1.5, f32::MAX).unwrap();
+        assert_eq!(2.0, hit.t());
+        assert_eq!(Vec3::new(4.0, 4.0, 4.0), hit.material().emitted(&r, &hit));
+    }
+
+    #[test]
+    fn test_obj_errors() {
+        let err = read_obj("v 0 0 0\nv 1 0 0\nf 1 2 3\n".as_bytes(), no_mtl)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            ObjError::IndexOutOfRange { line: 3, index: 3 }
+        ));
+        let err = read_obj("v 0 0 0\nf 1 -2 1\n".as_bytes(), no_mtl)
+            .err()
+            .unwrap();
+        assert!(matches!(
+            err,
+            ObjError::IndexOutOfRange { line: 2, index: -2 }
+        ));
+        let err = read_obj("v 0 zero 0\n".as_bytes(), no_mtl).err().unwrap();
+        assert!(matches!(err, ObjError::Parse { line: 1, .. }));
+        let err = read_obj("usemtl gold\n".as_bytes(), no_mtl).err().unwrap();
+        assert_eq!("line 1: unknown material gold", err.to_string());
+    }
+
+    #[test]
+    fn test_load_obj() {
+        let dir = std::env::temp_dir().join(format!("rt-obj-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("scene.obj"), OBJ).unwrap();
+        let err = load_obj(dir.join("scene.obj")).err().unwrap();
+        match err {
+            ObjError::Io(path, _) => assert_eq!(dir.join("scene.mtl"), path),
+            err => panic!("{}", err),
+        }
+        fs::write(dir.join("scene.mtl"), MTL).unwrap();
+        assert_eq!(2, load_obj(dir.join("scene.obj")).unwrap().len());
+        assert!(matches!(
+            load_obj(dir.join("missing.obj")),
+            Err(ObjError::Io(..))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,469 @@
+use crate::{
+    sampler,
+    texture::{Footprint, Texture},
+    vec3::Vec3,
+};
+
+// Scalar patterns are textures that come out grey; a `ColorRamp` turns them
+// into colors. The tiling patterns are laid out over the surface coordinates
+// and the cellular noise over space.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WorleyFeature {
+    // The distance to the nearest feature point, which gives round cells.
+    F1,
+    // The distance to the second nearest, which gives ridged cells.
+    F2,
+    // Their difference, which is zero along the cell walls.
+    F2MinusF1,
+}
+
+// Cellular noise: one random feature point in each cube of `scale`, and a
+// grey level from the distances to the nearest ones, relative to `scale` and
+// clamped to [0, 1].
+pub struct Worley {
+    seed: u64,
+    scale: f32,
+    feature: WorleyFeature,
+}
+
+impl Worley {
+    pub fn new(seed: u64, scale: f32) -> Self {
+        Worley {
+            seed,
+            scale,
+            feature: WorleyFeature::F1,
+        }
+    }
+
+    pub fn with_feature(mut self, feature: WorleyFeature) -> Self {
+        self.feature = feature;
+        self
+    }
+
+    // The two smallest distances to feature points, in cells.
+    pub fn distances(&self, p: &Vec3) -> (f32, f32) {
+        let q = *p / self.scale;
+        let cell = [q.x().floor(), q.y().floor(), q.z().floor()];
+        let (mut f1, mut f2) = (f32::MAX, f32::MAX);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let c = [
+                        cell[0] as i64 + dx,
+                        cell[1] as i64 + dy,
+                        cell[2] as i64 + dz,
+                    ];
+                    let h = hash(self.seed, c);
+                    let jitter =
+                        [0, 21, 42].map(|shift| ((h >> shift) & 0x1f_ffff) as f32 / 2_097_152.0);
+                    let point = Vec3::new(
+                        c[0] as f32 + jitter[0],
+                        c[1] as f32 + jitter[1],
+                        c[2] as f32 + jitter[2],
+                    );
+                    let d = (point - q).length();
+                    if d < f1 {
+                        f2 = f1;
+                        f1 = d;
+                    } else if d < f2 {
+                        f2 = d;
+                    }
+                }
+            }
+        }
+        (f1, f2)
+    }
+}
+
+impl Texture for Worley {
+    fn value(&self, _: f32, _: f32, p: &Vec3) -> Vec3 {
+        let (f1, f2) = self.distances(p);
+        let value = match self.feature {
+            WorleyFeature::F1 => f1,
+            WorleyFeature::F2 => f2,
+            WorleyFeature::F2MinusF1 => f2 - f1,
+        };
+        grey(value.clamp(0.0, 1.0))
+    }
+}
+
+fn hash(seed: u64, cell: [i64; 3]) -> u64 {
+    cell.iter().fold(seed, |z, c| sampler::mix(z, *c as u64))
+}
+
+fn grey(value: f32) -> Vec3 {
+    Vec3::new(value, value, value)
+}
+
+// Rows of bricks of `size` in (u, v), every other row shifted by half a
+// brick, with mortar `mortar_width` wide between them.
+pub struct Bricks {
+    size: (f32, f32),
+    mortar_width: f32,
+    brick: Box<dyn Texture>,
+    mortar: Box<dyn Texture>,
+}
+
+impl Bricks {
+    pub fn new(
+        size: (f32, f32),
+        mortar_width: f32,
+        brick: Box<dyn Texture>,
+        mortar: Box<dyn Texture>,
+    ) -> Self {
+        Bricks {
+            size,
+            mortar_width,
+            brick,
+            mortar,
+        }
+    }
+
+    fn texture_at(&self, u: f32, v: f32) -> &dyn Texture {
+        let y = v / self.size.1;
+        let row = y.floor();
+        let x = u / self.size.0 + if row.rem_euclid(2.0) == 1.0 { 0.5 } else { 0.0 };
+        // Distances to the nearest joints, in (u, v) units.
+        let dx = (x - x.round()).abs() * self.size.0;
+        let dy = (y - y.round()).abs() * self.size.1;
+        if dx.min(dy) < 0.5 * self.mortar_width {
+            self.mortar.as_ref()
+        } else {
+            self.brick.as_ref()
+        }
+    }
+}
+
+impl Texture for Bricks {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3 {
+        self.texture_at(u, v).value(u, v, p)
+    }
+
+    fn filtered(&self, u: f32, v: f32, p: &Vec3, footprint: &Footprint) -> Vec3 {
+        self.texture_at(u, v).filtered(u, v, p, footprint)
+    }
+}
+
+// Bands `width` wide across u, alternating between two textures.
+pub struct Stripes {
+    width: f32,
+    a: Box<dyn Texture>,
+    b: Box<dyn Texture>,
+}
+
+impl Stripes {
+    pub fn new(width: f32, a: Box<dyn Texture>, b: Box<dyn Texture>) -> Self {
+        Stripes { width, a, b }
+    }
+
+    fn texture_at(&self, u: f32) -> &dyn Texture {
+        if (u / self.width).floor().rem_euclid(2.0) == 0.0 {
+            self.a.as_ref()
+        } else {
+            self.b.as_ref()
+        }
+    }
+}
+
+impl Texture for Stripes {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3 {
+        self.texture_at(u).value(u, v, p)
+    }
+
+    fn filtered(&self, u: f32, v: f32, p: &Vec3, footprint: &Footprint) -> Vec3 {
+        self.texture_at(u).filtered(u, v, p, footprint)
+    }
+}
+
+// Dots of `radius` on a square grid of `spacing` in (u, v).
+pub struct PolkaDots {
+    spacing: f32,
+    radius: f32,
+    dot: Box<dyn Texture>,
+    background: Box<dyn Texture>,
+}
+
+impl PolkaDots {
+    pub fn new(
+        spacing: f32,
+        radius: f32,
+        dot: Box<dyn Texture>,
+        background: Box<dyn Texture>,
+    ) -> Self {
+        PolkaDots {
+            spacing,
+            radius,
+            dot,
+            background,
+        }
+    }
+
+    fn texture_at(&self, u: f32, v: f32) -> &dyn Texture {
+        let du = u - self.spacing * (u / self.spacing - 0.5).round() - 0.5 * self.spacing;
+        let dv = v - self.spacing * (v / self.spacing - 0.5).round() - 0.5 * self.spacing;
+        if du * du + dv * dv < self.radius * self.radius {
+            self.dot.as_ref()
+        } else {
+            self.background.as_ref()
+        }
+    }
+}
+
+impl Texture for PolkaDots {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3 {
+        self.texture_at(u, v).value(u, v, p)
+    }
+
+    fn filtered(&self, u: f32, v: f32, p: &Vec3, footprint: &Footprint) -> Vec3 {
+        self.texture_at(u, v).filtered(u, v, p, footprint)
+    }
+}
+
+// Hexagons with corners `size` from their centers, pointing along v, with
+// grout `mortar_width` wide between them.
+pub struct HexTiles {
+    size: f32,
+    mortar_width: f32,
+    tile: Box<dyn Texture>,
+    mortar: Box<dyn Texture>,
+}
+
+impl HexTiles {
+    pub fn new(
+        size: f32,
+        mortar_width: f32,
+        tile: Box<dyn Texture>,
+        mortar: Box<dyn Texture>,
+    ) -> Self {
+        HexTiles {
+            size,
+            mortar_width,
+            tile,
+            mortar,
+        }
+    }
+
+    // The distance from a point to the nearest edge of its hexagon.
+    fn edge_distance(&self, u: f32, v: f32) -> f32 {
+        let sqrt3 = 3f32.sqrt();
+        // Axial coordinates, rounded to the nearest center in cube
+        // coordinates.
+        let q = (sqrt3 / 3.0 * u - v / 3.0) / self.size;
+        let r = 2.0 / 3.0 * v / self.size;
+        let s = -q - r;
+        let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+        let (eq, er, es) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+        if eq > er && eq > es {
+            rq = -rr - rs;
+        } else if er > es {
+            rr = -rq - rs;
+        }
+        let cu = self.size * sqrt3 * (rq + rr / 2.0);
+        let cv = self.size * 1.5 * rr;
+        let (du, dv) = (u - cu, v - cv);
+        // Edge normals at 0, 60 and 120 degrees.
+        let reach = [(1.0, 0.0), (0.5, 0.5 * sqrt3), (-0.5, 0.5 * sqrt3)]
+            .iter()
+            .map(|(nu, nv)| (du * nu + dv * nv).abs())
+            .fold(0.0f32, f32::max);
+        0.5 * sqrt3 * self.size - reach
+    }
+
+    fn texture_at(&self, u: f32, v: f32) -> &dyn Texture {
+        if self.edge_distance(u, v) < 0.5 * self.mortar_width {
+            self.mortar.as_ref()
+        } else {
+            self.tile.as_ref()
+        }
+    }
+}
+
+impl Texture for HexTiles {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3 {
+        self.texture_at(u, v).value(u, v, p)
+    }
+
+    fn filtered(&self, u: f32, v: f32, p: &Vec3, footprint: &Footprint) -> Vec3 {
+        self.texture_at(u, v).filtered(u, v, p, footprint)
+    }
+}
+
+// Remaps the luminance of another texture, such as one of the grey patterns,
+// to colors interpolated between stops. Values beyond the first and last
+// stop take their colors.
+pub struct ColorRamp {
+    input: Box<dyn Texture>,
+    stops: Vec<(f32, Vec3)>,
+}
+
+impl ColorRamp {
+    pub fn new(input: Box<dyn Texture>, mut stops: Vec<(f32, Vec3)>) -> Self {
+        assert!(!stops.is_empty(), "a color ramp needs a stop");
+        assert!(
+            stops.iter().all(|(at, _)| !at.is_nan()),
+            "color ramp stops must not be NaN"
+        );
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        ColorRamp { input, stops }
+    }
+
+    pub fn color(&self, value: f32) -> Vec3 {
+        let i = self.stops.partition_point(|(at, _)| *at <= value);
+        if i == 0 {
+            return self.stops[0].1;
+        }
+        if i == self.stops.len() {
+            return self.stops[i - 1].1;
+        }
+        let ((a, ca), (b, cb)) = (self.stops[i - 1], self.stops[i]);
+        let t = (value - a) / (b - a);
+        (1.0 - t) * ca + t * cb
+    }
+}
+
+impl Texture for ColorRamp {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3 {
+        self.color(self.input.value(u, v, p).luminance())
+    }
+
+    fn filtered(&self, u: f32, v: f32, p: &Vec3, footprint: &Footprint) -> Vec3 {
+        self.color(self.input.filtered(u, v, p, footprint).luminance())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bricks, ColorRamp, HexTiles, PolkaDots, Stripes, Worley, WorleyFeature};
+    use crate::{
+        texture::{Footprint, SolidColor, Texture},
+        vec3::Vec3,
+    };
+
+    fn solid(value: f32) -> Box<SolidColor> {
+        Box::new(SolidColor::new(Vec3::new(value, value, value)))
+    }
+
+    fn grey_at<T: Texture>(texture: &T, u: f32, v: f32) -> f32 {
+        texture.value(u, v, &Vec3::zero()).r()
+    }
+
+    #[test]
+    fn test_worley() {
+        let worley = Worley::new(5, 2.0);
+        let mut walls = 0;
+        for i in 0..200 {
+            let p = Vec3::new(i as f32 * 0.37, i as f32 * 0.11, -(i as f32) * 0.23);
+            let (f1, f2) = worley.distances(&p);
+            assert!(f1 <= f2 && f1 <= 3f32.sqrt());
+            assert_eq!(f1.min(1.0), worley.value(0.0, 0.0, &p).r());
+            let edges = Worley::new(5, 2.0).with_feature(WorleyFeature::F2MinusF1);
+            if edges.value(0.0, 0.0, &p).r() < 0.1 {
+                walls += 1;
+            }
+        }
+        assert!(walls > 0 && walls < 100);
+        let p = Vec3::new(1.3, 2.7, -0.4);
+        assert_eq!(worley.distances(&p), Worley::new(5, 2.0).distances(&p));
+        assert_ne!(worley.distances(&p), Worley::new(6, 2.0).distances(&p));
+    }
+
+    #[test]
+    fn test_tilings() {
+        let bricks = Bricks::new((2.0, 1.0), 0.1, solid(1.0), solid(0.0));
+        assert_eq!(1.0, grey_at(&bricks, 1.0, 0.5));
+        assert_eq!(0.0, grey_at(&bricks, 2.02, 0.5));
+        // The next row is shifted, so the joint there is a brick here.
+        assert_eq!(1.0, grey_at(&bricks, 2.02, 1.5));
+        assert_eq!(0.0, grey_at(&bricks, 1.0, 1.01));
+
+        let stripes = Stripes::new(0.5, solid(1.0), solid(0.0));
+        assert_eq!(1.0, grey_at(&stripes, 0.25, 7.0));
+        assert_eq!(0.0, grey_at(&stripes, 0.75, 7.0));
+        assert_eq!(0.0, grey_at(&stripes, -0.25, 7.0));
+
+        let dots = PolkaDots::new(1.0, 0.2, solid(1.0), solid(0.0));
+        assert_eq!(1.0, grey_at(&dots, 3.5, -1.4));
+        assert_eq!(0.0, grey_at(&dots, 3.0, -1.4));
+
+        let hexes = HexTiles::new(1.0, 0.1, solid(1.0), solid(0.0));
+        assert_eq!(1.0, grey_at(&hexes, 0.0, 0.0));
+        // Halfway to the center of the next tile over.
+        assert_eq!(0.0, grey_at(&hexes, 0.5 * 3f32.sqrt(), 0.0));
+        assert_eq!(1.0, grey_at(&hexes, 3f32.sqrt(), 0.0));
+        assert_eq!(1.0, grey_at(&hexes, 0.5 * 3f32.sqrt(), 1.5));
+        assert_eq!(0.0, grey_at(&hexes, 0.0, 0.99));
+    }
+
+    #[test]
+    fn test_color_ramp() {
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let blue = Vec3::new(0.0, 0.0, 1.0);
+        let ramp = ColorRamp::new(solid(0.5), vec![(0.75, blue), (0.25, red)]);
+        assert_eq!(0.5 * (red + blue), ramp.value(0.0, 0.0, &Vec3::zero()));
+        assert_eq!(red, ramp.color(0.0));
+        assert_eq!(blue, ramp.color(2.0));
+        assert_eq!(0.75 * red + 0.25 * blue, ramp.color(0.375));
+    }
+
+    #[test]
+    #[should_panic(expected = "NaN")]
+    fn test_color_ramp_nan_stop() {
+        ColorRamp::new(solid(0.5), vec![(f32::NAN, Vec3::zero())]);
+    }
+
+    // Black when looked up at a point, white when filtered.
+    struct Filtered;
+
+    impl Texture for Filtered {
+        fn value(&self, _: f32, _: f32, _: &Vec3) -> Vec3 {
+            Vec3::zero()
+        }
+
+        fn filtered(&self, _: f32, _: f32, _: &Vec3, _: &Footprint) -> Vec3 {
+            Vec3::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    #[test]
+    fn test_filtered_children() {
+        let footprint = Footprint {
+            dudx: 0.01,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy: 0.01,
+        };
+        let patterns: Vec<Box<dyn Texture>> = vec![
+            Box::new(Bricks::new(
+                (1.0, 0.5),
+                0.1,
+                Box::new(Filtered),
+                Box::new(Filtered),
+            )),
+            Box::new(Stripes::new(0.5, Box::new(Filtered), Box::new(Filtered))),
+            Box::new(PolkaDots::new(
+                1.0,
+                0.2,
+                Box::new(Filtered),
+                Box::new(Filtered),
+            )),
+            Box::new(HexTiles::new(
+                1.0,
+                0.1,
+                Box::new(Filtered),
+                Box::new(Filtered),
+            )),
+            Box::new(ColorRamp::new(
+                Box::new(Filtered),
+                vec![(0.0, Vec3::zero()), (1.0, Vec3::new(1.0, 1.0, 1.0))],
+            )),
+        ];
+        for pattern in patterns.iter() {
+            for &(u, v) in [(0.0, 0.0), (0.3, 0.7), (2.5, -1.25)].iter() {
+                assert_eq!(Vec3::zero(), pattern.value(u, v, &Vec3::zero()));
+                let filtered = pattern.filtered(u, v, &Vec3::zero(), &footprint);
+                assert_eq!(Vec3::new(1.0, 1.0, 1.0), filtered);
+            }
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use crate::{material::Material, mesh::TriangleMesh, tonemap::srgb_eotf, vec3::Vec3};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+pub fn load_ply<P: AsRef<Path>>(path: P, material: Box<dyn Material>) -> io::Result<TriangleMesh> {
+    read_ply(BufReader::new(File::open(path)?), material)
+}
+
+// Reads the vertex and face elements of an ASCII or binary little endian PLY
+// file; polygons are split into fans. Vertex normals and colors are kept when
+// present, with integer colors taken to be sRGB encoded over their full range.
+pub fn read_ply<R: BufRead>(
+    mut reader: R,
+    material: Box<dyn Material>,
+) -> io::Result<TriangleMesh> {
+    let header = read_header(&mut reader)?;
+    let mut values = match header.format {
+        Format::Ascii => {
+            let mut body = String::new();
+            reader.read_to_string(&mut body)?;
+            let numbers = body
+                .split_whitespace()
+                .map(|token| token.parse().map_err(|_| invalid("bad number")))
+                .collect::<io::Result<Vec<f64>>>()?;
+            Values::Ascii(numbers.into_iter())
+        }
+        Format::BinaryLittleEndian => Values::Binary(reader),
+    };
+
+    let mut vertices = vec![];
+    let mut normals = vec![];
+    let mut colors = vec![];
+    let mut indices = vec![];
+    for element in header.elements.iter() {
+        let find = |name: &str| element.properties.iter().position(|p| p.name == name);
+        let position = [find("x"), find("y"), find("z")];
+        let normal = [find("nx"), find("ny"), find("nz")];
+        let color = [find("red"), find("green"), find("blue")];
+        let mut row = vec![0.0; element.properties.len()];
+        for _ in 0..element.count {
+            for (value, property) in row.iter_mut().zip(element.properties.iter()) {
+                *value = match property.kind {
+                    Kind::Scalar(ty) => values.read(ty)?,
+                    Kind::List(count_ty, item_ty) => {
+                        let count = values.read(count_ty)?;
+                        if count < 0.0 || count.fract() != 0.0 {
+                            return Err(invalid("bad list count"));
+                        }
+                        // Not preallocated: the count is only as good as the data.
+                        let mut polygon = vec![];
+                        for _ in 0..count as usize {
+                            polygon.push(values.read(item_ty)?);
+                        }
+                        if element.name == "face"
+                            && (property.name == "vertex_indices"
+                                || property.name == "vertex_index")
+                        {
+                            push_polygon(&mut indices, &polygon, element_count(&header, "vertex"))?;
+                        }
+                        0.0
+                    }
+                };
+            }
+            if element.name != "vertex" {
+                continue;
+            }
+            let get = |i: [Option<usize>; 3]| match i {
+                [Some(x), Some(y), Some(z)] => {
+                    Some(Vec3::new(row[x] as f32, row[y] as f32, row[z] as f32))
+                }
+                _ => None,
+            };
+            vertices.push(get(position).ok_or_else(|| invalid("vertex without a position"))?);
+            if let Some(n) = get(normal) {
+                normals.push(n);
+            }
+            if let Some(c) = get(color) {
+                let ty = element.properties[color[0].unwrap()].scalar_type();
+                colors.push(match ty.and_then(Type::max) {
+                    Some(max) => Vec3::new(
+                        srgb_eotf(c[0] / max),
+                        srgb_eotf(c[1] / max),
+                        srgb_eotf(c[2] / max),
+                    ),
+                    None => c,
+                });
+            }
+        }
+    }
+
+    let mut mesh = TriangleMesh::new(vertices, indices, material);
+    if !normals.is_empty() {
+        mesh = mesh.with_normals(normals);
+    }
+    if !colors.is_empty() {
+        mesh = mesh.with_colors(colors);
+    }
+    Ok(mesh)
+}
+
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Type {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Type {
+    fn parse(name: &str) -> io::Result<Self> {
+        Ok(match name {
+            "char" | "int8" => Type::I8,
+            "uchar" | "uint8" => Type::U8,
+            "short" | "int16" => Type::I16,
+            "ushort" | "uint16" => Type::U16,
+            "int" | "int32" => Type::I32,
+            "uint" | "uint32" => Type::U32,
+            "float" | "float32" => Type::F32,
+            "double" | "float64" => Type::F64,
+            _ => return Err(invalid(&format!("unknown property type {}", name))),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Type::I8 | Type::U8 => 1,
+            Type::I16 | Type::U16 => 2,
+            Type::I32 | Type::U32 | Type::F32 => 4,
+            Type::F64 => 8,
+        }
+    }
+
+    // The largest value of an integer type.
+    fn max(self) -> Option<f32> {
+        match self {
+            Type::I8 => Some(i8::MAX as f32),
+            Type::U8 => Some(u8::MAX as f32),
+            Type::I16 => Some(i16::MAX as f32),
+            Type::U16 => Some(u16::MAX as f32),
+            Type::I32 => Some(i32::MAX as f32),
+            Type::U32 => Some(u32::MAX as f32),
+            Type::F32 | Type::F64 => None,
+        }
+    }
+}
+
+enum Kind {
+    Scalar(Type),
+    // The type of the count, then that of the items.
+    List(Type, Type),
+}
+
+struct Property {
+    name: String,
+    kind: Kind,
+}
+
+impl Property {
+    fn scalar_type(&self) -> Option<Type> {
+        match self.kind {
+            Kind::Scalar(ty) => Some(ty),
+            Kind::List(..) => None,
+        }
+    }
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> io::Result<Header> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim_end() != "ply" {
+        return Err(invalid("not a PLY file"));
+    }
+    let mut format = None;
+    let mut elements: Vec<Element> = vec![];
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("unexpected end of header"));
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields[..] {
+            ["format", "ascii", _] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(Format::BinaryLittleEndian),
+            ["format", ..] => return Err(invalid("unsupported PLY format")),
+            ["element", name, count] => elements.push(Element {
+                name: name.to_string(),
+                count: count.parse().map_err(|_| invalid("bad element count"))?,
+                properties: vec![],
+            }),
+            ["property", "list", count_ty, item_ty, name] => {
+                let kind = Kind::List(Type::parse(count_ty)?, Type::parse(item_ty)?);
+                push_property(&mut elements, name, kind)?;
+            }
+            ["property", ty, name] => {
+                push_property(&mut elements, name, Kind::Scalar(Type::parse(ty)?))?
+            }
+            ["end_header"] => break,
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(invalid(&format!("bad header line: {}", line.trim_end()))),
+        }
+    }
+    match format {
+        Some(format) => Ok(Header { format, elements }),
+        None => Err(invalid("missing format")),
+    }
+}
+
+fn push_property(elements: &mut [Element], name: &str, kind: Kind) -> io::Result<()> {
+    match elements.last_mut() {
+        Some(element) => {
+            element.properties.push(Property {
+                name: name.to_string(),
+                kind,
+            });
+            Ok(())
+        }
+        None => Err(invalid("property before any element")),
+    }
+}
+
+fn element_count(header: &Header, name: &str) -> usize {
+    header
+        .elements
+        .iter()
+        .find(|e| e.name == name)
+        .map_or(0, |e| e.count)
+}
+
+fn push_polygon(
+    indices: &mut Vec<[u32; 3]>,
+    polygon: &[f64],
+    vertex_count: usize,
+) -> io::Result<()> {
+    if polygon
+        .iter()
+        .any(|&i| i < 0.0 || i as usize >= vertex_count)
+    {
+        return Err(invalid("face index out of range"));
+    }
+    for i in 1..polygon.len().saturating_sub(1) {
+        indices.push([polygon[0] as u32, polygon[i] as u32, polygon[i + 1] as u32]);
+    }
+    Ok(())
+}
+
+enum Values<R> {
+    Ascii(std::vec::IntoIter<f64>),
+    Binary(R),
+}
+
+impl<R: Read> Values<R> {
+    // Every type fits losslessly into an f64.
+    fn read(&mut self, ty: Type) -> io::Result<f64> {
+        match self {
+            Values::Ascii(numbers) => numbers
+                .next()
+                .ok_or_else(|| invalid("unexpected end of data")),
+            Values::Binary(reader) => {
+                let mut b = [0u8; 8];
+                reader.read_exact(&mut b[..ty.size()])?;
+                Ok(match ty {
+                    Type::I8 => b[0] as i8 as f64,
+                    Type::U8 => b[0] as f64,
+                    Type::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+                    Type::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+                    Type::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Type::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Type::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+                    Type::F64 => f64::from_le_bytes(b),
+                })
+            }
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_ply;
+    use crate::{hitable::Hitable, ray::Ray, test_support::grey, vec3::Vec3};
+
+    // A unit quad at z = 0, white at x = 1 and black at x = 0.
+    const ASCII: &str = "ply
+format ascii 1.0
+comment a quad
+element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0 0 1 0 0 0
+1 0 0 0 0 1 255 255 255
+1 1 0 0 0 1 255 255 255
+0 1 0 0 0 1 0 0 0
+4 0 1 2 3
+";
+
+    fn binary() -> Vec<u8> {
+        let mut data = b"ply
+format binary_little_endian 1.0
+element vertex 3
+property double x
+property double y
+property double z
+property short unused
+element face 1
+property list uchar uint vertex_index
+end_header
+"
+        .to_vec();
+        for v in [[0.0f64, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]].iter() {
+            for x in v.iter() {
+                data.extend_from_slice(&x.to_le_bytes());
+            }
+            data.extend_from_slice(&(-7i16).to_le_bytes());
+        }
+        data.push(3);
+        for i in 0..3u32 {
+            data.extend_from_slice(&i.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_read_ascii() {
+        let mesh = read_ply(ASCII.as_bytes(), grey()).unwrap();
+        assert_eq!(2, mesh.len());
+        assert_eq!(4, mesh.normals().unwrap().len());
+        let r = Ray::new(Vec3::new(0.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = mesh.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(&Vec3::new(0.0, 0.0, 1.0), hit.normal());
+        assert!((hit.color().r() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_read_binary() {
+        let mesh = read_ply(&binary()[..], grey()).unwrap();
+        assert_eq!(1, mesh.len());
+        assert_eq!(&[Vec3::new(0.0, 1.0, 0.0)], &mesh.vertices()[2..]);
+        assert!(mesh.normals().is_none() && mesh.colors().is_none());
+        let r = Ray::new(Vec3::new(0.25, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = mesh.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(&Vec3::new(1.0, 1.0, 1.0), hit.color());
+    }
+
+    #[test]
+    fn test_reject_garbage() {
+        let big_endian = ASCII.replace("ascii", "binary_big_endian");
+        assert!(read_ply(big_endian.as_bytes(), grey()).is_err());
+        let bad_index = ASCII.replace("4 0 1 2 3", "4 0 1 2 4");
+        assert!(read_ply(bad_index.as_bytes(), grey()).is_err());
+        let truncated = binary();
+        assert!(read_ply(&truncated[..truncated.len() - 1], grey()).is_err());
+        assert!(read_ply(&b"obj\n"[..], grey()).is_err());
+        let bad_count = ASCII.replace("4 0 1 2 3", "-1 0 1 2 3");
+        assert!(read_ply(bad_count.as_bytes(), grey()).is_err());
+        // A count far beyond the data fails without trying to allocate for it.
+        let huge = ASCII
+            .replace("list uchar", "list uint")
+            .replace("4 0 1 2 3", "4000000000 0 1 2 3");
+        assert!(read_ply(huge.as_bytes(), grey()).is_err());
+    }
+
+    #[test]
+    fn test_color_types() {
+        let r = Ray::new(Vec3::new(0.5, 0.5, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let wide = ASCII
+            .replace("uchar red", "ushort red")
+            .replace("uchar green", "ushort green")
+            .replace("uchar blue", "ushort blue")
+            .replace("255 255 255", "65535 65535 65535");
+        let float = ASCII
+            .replace("uchar red", "float red")
+            .replace("uchar green", "float green")
+            .replace("uchar blue", "float blue")
+            .replace("255 255 255", "1 1 1");
+        for data in [wide, float].iter() {
+            let mesh = read_ply(data.as_bytes(), grey()).unwrap();
+            let hit = mesh.hit(&r, 0.001, f32::MAX).unwrap();
+            assert!((hit.color().r() - 0.5).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,732 @@
+use crate::{
+    aabb::Aabb,
+    hitable::{HitRecord, Hitable},
+    material::Material,
+    ray::Ray,
+    vec3::Vec3,
+};
+use std::f32::consts::PI;
+
+// The shapes below are hit in a local frame in which their axis is +z, and
+// their u coordinate runs around that axis counter-clockwise from the frame's
+// first axis.
+#[derive(Clone, Debug)]
+struct Frame {
+    origin: Vec3,
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+}
+
+impl Frame {
+    fn new(origin: Vec3, axis: &Vec3) -> Self {
+        let z = axis.unit_vector();
+        // Any vector not parallel to the axis will do.
+        let helper = if z.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let y = Vec3::cross(&z, &helper).unit_vector();
+        let x = Vec3::cross(&y, &z);
+        Frame { origin, x, y, z }
+    }
+
+    fn to_local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(
+            Vec3::dot(v, &self.x),
+            Vec3::dot(v, &self.y),
+            Vec3::dot(v, &self.z),
+        )
+    }
+
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        v.x() * self.x + v.y() * self.y + v.z() * self.z
+    }
+
+    fn local_ray(&self, r: &Ray) -> (Vec3, Vec3) {
+        (
+            self.to_local(&(*r.origin() - self.origin)),
+            self.to_local(r.direction()),
+        )
+    }
+
+    // The box around a disk of `radius` centered at `center` and facing
+    // along the frame's axis.
+    fn disk_bounds(&self, center: &Vec3, radius: f32) -> Aabb {
+        let mut extent = Vec3::zero();
+        for i in 0..3 {
+            extent[i] = radius * (1.0 - self.z[i] * self.z[i]).max(0.0).sqrt();
+        }
+        Aabb::new(*center - extent, *center + extent)
+    }
+}
+
+// Angle around the z axis as a fraction of a turn in [0, 1).
+fn turn(x: f32, y: f32) -> f32 {
+    let phi = y.atan2(x);
+    if phi < 0.0 {
+        (phi + 2.0 * PI) / (2.0 * PI)
+    } else {
+        phi / (2.0 * PI)
+    }
+}
+
+// How a point moves as its turn around the z axis grows, its dp/du.
+fn around(p: &Vec3) -> Vec3 {
+    2.0 * PI * Vec3::new(-p.y(), p.x(), 0.0)
+}
+
+// The unit vector away from the z axis at a turn of u.
+fn outwards(u: f32) -> Vec3 {
+    let phi = 2.0 * PI * u;
+    Vec3::new(phi.cos(), phi.sin(), 0.0)
+}
+
+// Flat shapes have boxes of no thickness; give them a little.
+const FLAT_PAD: f32 = 1e-4;
+
+// An infinite plane through `point`, so it has no bounding box and must stay
+// out of BVHs. u and v are distances along two directions in the plane.
+pub struct Plane {
+    frame: Frame,
+    material: Box<dyn Material>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, material: Box<dyn Material>) -> Self {
+        Plane {
+            frame: Frame::new(point, &normal),
+            material,
+        }
+    }
+}
+
+impl Hitable for Plane {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.local_ray(r);
+        let t = -o.z() / d.z();
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+        let p = o + t * d;
+        Some(
+            HitRecord::new(
+                t,
+                r.point_at_parameter(t),
+                self.frame.z,
+                self.material.as_ref(),
+            )
+            .with_uv(p.x(), p.y())
+            .with_derivatives(self.frame.x, self.frame.y),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+// u is the angle around the center and v the distance from it relative to
+// the radius.
+pub struct Disk {
+    frame: Frame,
+    radius: f32,
+    material: Box<dyn Material>,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f32, material: Box<dyn Material>) -> Self {
+        Disk {
+            frame: Frame::new(center, &normal),
+            radius,
+            material,
+        }
+    }
+}
+
+impl Hitable for Disk {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.local_ray(r);
+        let t = -o.z() / d.z();
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+        let p = o + t * d;
+        let dist = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        if dist > self.radius {
+            return None;
+        }
+        let u = turn(p.x(), p.y());
+        Some(
+            HitRecord::new(
+                t,
+                r.point_at_parameter(t),
+                self.frame.z,
+                self.material.as_ref(),
+            )
+            .with_uv(u, dist / self.radius)
+            .with_derivatives(
+                self.frame.to_world(&around(&p)),
+                self.frame.to_world(&(self.radius * outwards(u))),
+            ),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(
+            self.frame
+                .disk_bounds(&self.frame.origin, self.radius)
+                .padded(FLAT_PAD),
+        )
+    }
+}
+
+// A cylinder from `base` to `top` closed by flat caps. u is the angle around
+// the axis; v the height along it on the side and the distance from the axis
+// on the caps.
+pub struct Cylinder {
+    frame: Frame,
+    height: f32,
+    radius: f32,
+    material: Box<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, top: Vec3, radius: f32, material: Box<dyn Material>) -> Self {
+        Cylinder {
+            frame: Frame::new(base, &(top - base)),
+            height: (top - base).length(),
+            radius,
+            material,
+        }
+    }
+}
+
+impl Hitable for Cylinder {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.local_ray(r);
+        // t, the normal, u, v and dp/dv, all in the local frame.
+        let mut closest: Option<(f32, Vec3, f32, f32, Vec3)> = None;
+        let mut t_max = t_max;
+
+        // The side: x^2 + y^2 = radius^2 between the caps.
+        let a = d.x() * d.x() + d.y() * d.y();
+        let b = o.x() * d.x() + o.y() * d.y();
+        let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+        for t in quadratic_roots(a, b, c).iter().flatten() {
+            let p = o + *t * d;
+            if *t > t_min && *t < t_max && p.z() >= 0.0 && p.z() <= self.height {
+                let normal = Vec3::new(p.x(), p.y(), 0.0) / self.radius;
+                let dpdv = Vec3::new(0.0, 0.0, self.height);
+                closest = Some((*t, normal, turn(p.x(), p.y()), p.z() / self.height, dpdv));
+                t_max = *t;
+            }
+        }
+        for &(z, sign) in [(0.0, -1.0), (self.height, 1.0)].iter() {
+            let t = (z - o.z()) / d.z();
+            if !(t > t_min && t < t_max) {
+                continue;
+            }
+            let p = o + t * d;
+            let dist = (p.x() * p.x() + p.y() * p.y()).sqrt();
+            if dist <= self.radius {
+                let normal = Vec3::new(0.0, 0.0, sign);
+                let u = turn(p.x(), p.y());
+                closest = Some((t, normal, u, dist / self.radius, self.radius * outwards(u)));
+                t_max = t;
+            }
+        }
+
+        let (t, normal, u, v, dpdv) = closest?;
+        let p = o + t * d;
+        Some(
+            HitRecord::new(
+                t,
+                r.point_at_parameter(t),
+                self.frame.to_world(&normal),
+                self.material.as_ref(),
+            )
+            .with_uv(u, v)
+            .with_derivatives(self.frame.to_world(&around(&p)), self.frame.to_world(&dpdv)),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let top = self.frame.origin + self.height * self.frame.z;
+        Some(Aabb::surrounding(
+            &self.frame.disk_bounds(&self.frame.origin, self.radius),
+            &self.frame.disk_bounds(&top, self.radius),
+        ))
+    }
+}
+
+// A cone with its base of `radius` at `base`, closed by a flat disk, and its
+// tip at `apex`. u is the angle around the axis; v the height along it on the
+// side and the distance from the axis on the base.
+pub struct Cone {
+    frame: Frame,
+    height: f32,
+    radius: f32,
+    material: Box<dyn Material>,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, apex: Vec3, radius: f32, material: Box<dyn Material>) -> Self {
+        Cone {
+            frame: Frame::new(base, &(apex - base)),
+            height: (apex - base).length(),
+            radius,
+            material,
+        }
+    }
+}
+
+impl Hitable for Cone {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.local_ray(r);
+        // t, the normal, u, v and dp/dv, all in the local frame.
+        let mut closest: Option<(f32, Vec3, f32, f32, Vec3)> = None;
+        let mut t_max = t_max;
+
+        // The side: x^2 + y^2 = k^2 (height - z)^2 below the apex.
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let h = self.height - o.z();
+        let a = d.x() * d.x() + d.y() * d.y() - k2 * d.z() * d.z();
+        let b = o.x() * d.x() + o.y() * d.y() + k2 * h * d.z();
+        let c = o.x() * o.x() + o.y() * o.y() - k2 * h * h;
+        for t in quadratic_roots(a, b, c).iter().flatten() {
+            let p = o + *t * d;
+            if *t > t_min && *t < t_max && p.z() >= 0.0 && p.z() <= self.height {
+                let rho = (p.x() * p.x() + p.y() * p.y()).sqrt().max(1e-12);
+                let normal = Vec3::new(p.x() / rho, p.y() / rho, k).unit_vector();
+                let u = turn(p.x(), p.y());
+                let dpdv = Vec3::new(0.0, 0.0, self.height) - self.radius * outwards(u);
+                closest = Some((*t, normal, u, p.z() / self.height, dpdv));
+                t_max = *t;
+            }
+        }
+        let t = -o.z() / d.z();
+        if t > t_min && t < t_max {
+            let p = o + t * d;
+            let dist = (p.x() * p.x() + p.y() * p.y()).sqrt();
+            if dist <= self.radius {
+                let normal = Vec3::new(0.0, 0.0, -1.0);
+                let u = turn(p.x(), p.y());
+                closest = Some((t, normal, u, dist / self.radius, self.radius * outwards(u)));
+            }
+        }
+
+        let (t, normal, u, v, dpdv) = closest?;
+        let p = o + t * d;
+        Some(
+            HitRecord::new(
+                t,
+                r.point_at_parameter(t),
+                self.frame.to_world(&normal),
+                self.material.as_ref(),
+            )
+            .with_uv(u, v)
+            .with_derivatives(self.frame.to_world(&around(&p)), self.frame.to_world(&dpdv)),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let apex = self.frame.origin + self.height * self.frame.z;
+        Some(Aabb::surrounding(
+            &self.frame.disk_bounds(&self.frame.origin, self.radius),
+            &Aabb::new(apex, apex),
+        ))
+    }
+}
+
+// A ring around `axis` through `center`: the points at `minor_radius` from the
+// circle of `major_radius`. u is the angle around the axis and v the angle
+// around the tube, starting on its outside.
+pub struct Torus {
+    frame: Frame,
+    major_radius: f32,
+    minor_radius: f32,
+    material: Box<dyn Material>,
+}
+
+impl Torus {
+    pub fn new(
+        center: Vec3,
+        axis: Vec3,
+        major_radius: f32,
+        minor_radius: f32,
+        material: Box<dyn Material>,
+    ) -> Self {
+        Torus {
+            frame: Frame::new(center, &axis),
+            major_radius,
+            minor_radius,
+            material,
+        }
+    }
+}
+
+impl Hitable for Torus {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let (o, d) = self.frame.local_ray(r);
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2), solved in double
+        // precision along a unit direction for a well conditioned quartic.
+        let len = d.length() as f64;
+        let (ox, oy, oz) = (o.x() as f64, o.y() as f64, o.z() as f64);
+        let (dx, dy, dz) = (d.x() as f64 / len, d.y() as f64 / len, d.z() as f64 / len);
+        let big = self.major_radius as f64 * self.major_radius as f64;
+        let small = self.minor_radius as f64 * self.minor_radius as f64;
+        let od = ox * dx + oy * dy + oz * dz;
+        let oo = ox * ox + oy * oy + oz * oz;
+        let k = oo + big - small;
+        let coefficients = [
+            1.0,
+            4.0 * od,
+            2.0 * k + 4.0 * od * od - 4.0 * big * (dx * dx + dy * dy),
+            4.0 * k * od - 8.0 * big * (ox * dx + oy * dy),
+            k * k - 4.0 * big * (ox * ox + oy * oy),
+        ];
+        let t = solve_quartic(&coefficients)
+            .into_iter()
+            .map(|s| (s / len) as f32)
+            .filter(|t| *t > t_min && *t < t_max)
+            .fold(None, |closest: Option<f32>, t| {
+                Some(closest.map_or(t, |c| c.min(t)))
+            })?;
+
+        let p = o + t * d;
+        let s = p.squared_length() + self.major_radius * self.major_radius
+            - self.minor_radius * self.minor_radius;
+        let twice_big = 2.0 * self.major_radius * self.major_radius;
+        let normal = Vec3::new(p.x() * (s - twice_big), p.y() * (s - twice_big), p.z() * s);
+        let rho = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        let u = turn(p.x(), p.y());
+        // Around the tube, in the plane through the axis.
+        let dpdv = 2.0 * PI * (Vec3::new(0.0, 0.0, rho - self.major_radius) - p.z() * outwards(u));
+        Some(
+            HitRecord::new(
+                t,
+                r.point_at_parameter(t),
+                self.frame.to_world(&normal).unit_vector(),
+                self.material.as_ref(),
+            )
+            .with_uv(u, turn(rho - self.major_radius, p.z()))
+            .with_derivatives(self.frame.to_world(&around(&p)), self.frame.to_world(&dpdv)),
+        )
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let mut extent = Vec3::zero();
+        for i in 0..3 {
+            let along = self.frame.z[i].abs();
+            extent[i] =
+                self.major_radius * (1.0 - along * along).max(0.0).sqrt() + self.minor_radius;
+        }
+        Some(Aabb::new(
+            self.frame.origin - extent,
+            self.frame.origin + extent,
+        ))
+    }
+}
+
+// Real roots of a t^2 + 2 b t + c, smallest first.
+fn quadratic_roots(a: f32, b: f32, c: f32) -> [Option<f32>; 2] {
+    if a == 0.0 {
+        return [if b != 0.0 { Some(-c / (2.0 * b)) } else { None }, None];
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return [None, None];
+    }
+    // Avoid cancellation by computing the larger root first.
+    let q = -(b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    [Some(t0.min(t1)), Some(t0.max(t1))]
+}
+
+// Real roots of c[0] x^4 + c[1] x^3 + c[2] x^2 + c[3] x + c[4] by Ferrari's
+// method, each polished with a few Newton steps.
+fn solve_quartic(c: &[f64; 5]) -> Vec<f64> {
+    let (a, b, cc, d) = (c[1] / c[0], c[2] / c[0], c[3] / c[0], c[4] / c[0]);
+    // Substitute x = y - a / 4 to get y^4 + p y^2 + q y + r.
+    let a2 = a * a;
+    let p = b - 3.0 / 8.0 * a2;
+    let q = cc - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * cc / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut roots = vec![];
+    if q.abs() < 1e-12 {
+        // Biquadratic: a quadratic in y^2.
+        let disc = p * p - 4.0 * r;
+        if disc >= 0.0 {
+            for z in [(-p + disc.sqrt()) / 2.0, (-p - disc.sqrt()) / 2.0].iter() {
+                if *z >= 0.0 {
+                    roots.push(z.sqrt());
+                    roots.push(-z.sqrt());
+                }
+            }
+        }
+    } else {
+        // Any root m > 0 of the resolvent cubic 8 m^3 + 8 p m^2 + (2 p^2 -
+        // 8 r) m - q^2 splits the quartic into two quadratics.
+        let m = largest_cubic_root(p, p * p / 4.0 - r, -q * q / 8.0);
+        if m <= 0.0 {
+            return vec![];
+        }
+        let s = (2.0 * m).sqrt();
+        for &(sign, offset) in [(1.0, 1.0), (-1.0, -1.0)].iter() {
+            // y^2 -+ s y + (p / 2 + m +- q / (2 s)) = 0
+            let constant = p / 2.0 + m + offset * q / (2.0 * s);
+            let disc = s * s - 4.0 * constant;
+            if disc >= 0.0 {
+                let root = disc.sqrt();
+                roots.push((sign * s + root) / 2.0);
+                roots.push((sign * s - root) / 2.0);
+            }
+        }
+    }
+    roots
+        .into_iter()
+        .map(|y| {
+            let mut x = y - a / 4.0;
+            for _ in 0..3 {
+                let f = (((c[0] * x + c[1]) * x + c[2]) * x + c[3]) * x + c[4];
+                let df = ((4.0 * c[0] * x + 3.0 * c[1]) * x + 2.0 * c[2]) * x + c[3];
+                if df == 0.0 {
+                    break;
+                }
+                x -= f / df;
+            }
+            x
+        })
+        .collect()
+}
+
+// The largest real root of m^3 + b m^2 + c m + d.
+fn largest_cubic_root(b: f64, c: f64, d: f64) -> f64 {
+    // Depressed: m = z - b / 3, z^3 + p z + q.
+    let p = c - b * b / 3.0;
+    let q = 2.0 * b * b * b / 27.0 - b * c / 3.0 + d;
+    let disc = q * q / 4.0 + p * p * p / 27.0;
+    let z = if disc > 0.0 {
+        let root = disc.sqrt();
+        (-q / 2.0 + root).cbrt() + (-q / 2.0 - root).cbrt()
+    } else {
+        // Three real roots; the largest is at k = 0.
+        let rho = (-p / 3.0).sqrt();
+        let theta = if rho == 0.0 {
+            0.0
+        } else {
+            (-q / (2.0 * rho * rho * rho)).clamp(-1.0, 1.0).acos()
+        };
+        2.0 * rho * (theta / 3.0).cos()
+    };
+    z - b / 3.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{solve_quartic, Cone, Cylinder, Disk, Plane, Torus};
+    use crate::{
+        bump::Bumped,
+        hitable::Hitable,
+        ray::Ray,
+        sampler::Sampler,
+        test_support::{assert_near, grey},
+        texture::Texture,
+        vec3::Vec3,
+    };
+    use rand::Rng;
+
+    // Rays from all around must find hits on the surface, inside the box,
+    // with unit normals and with derivatives spanning the surface.
+    fn check_hits<H: Hitable>(shape: &H, on_surface: impl Fn(&Vec3) -> bool) -> usize {
+        let mut rng = Sampler::new(9);
+        let bbox = shape.bounding_box().unwrap();
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin =
+                Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 10.0 - Vec3::new(5.0, 5.0, 5.0);
+            let target =
+                Vec3::new(rng.gen(), rng.gen(), rng.gen()) * 2.0 - Vec3::new(1.0, 1.0, 1.0);
+            let r = Ray::new(origin, target - origin);
+            if let Some(hit) = shape.hit(&r, 0.001, f32::MAX) {
+                hits += 1;
+                let p = hit.p();
+                assert!(on_surface(p), "{:?}", p);
+                for i in 0..3 {
+                    assert!(bbox.min()[i] - 1e-3 <= p[i] && p[i] <= bbox.max()[i] + 1e-3);
+                }
+                assert!((hit.normal().length() - 1.0).abs() < 1e-4);
+                let (dpdu, dpdv) = (hit.dpdu(), hit.dpdv());
+                for dp in [dpdu, dpdv].iter() {
+                    assert!(Vec3::dot(dp, hit.normal()).abs() <= 1e-3 * dp.length());
+                }
+                assert!(Vec3::cross(dpdu, dpdv).length() > 0.0);
+                assert!((0.0..=1.0).contains(&hit.u()) && (0.0..=1.0).contains(&hit.v()));
+            }
+        }
+        hits
+    }
+
+    #[test]
+    fn test_plane_and_disk() {
+        let plane = Plane::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), grey());
+        let r = Ray::new(Vec3::new(3.0, 1.0, 2.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = plane.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(2.0, hit.t());
+        assert_eq!(&Vec3::new(0.0, 1.0, 0.0), hit.normal());
+        assert!(plane.bounding_box().is_none());
+        let parallel = Ray::new(Vec3::new(3.0, 1.0, 2.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(plane.hit(&parallel, 0.001, f32::MAX).is_none());
+
+        let disk = Disk::new(
+            Vec3::new(0.0, 0.0, -2.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            1.0,
+            grey(),
+        );
+        let r = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = disk.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(2.0, hit.t());
+        assert!((hit.v() - 0.5).abs() < 1e-6);
+        let r = Ray::new(Vec3::new(0.0, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(disk.hit(&r, 0.001, f32::MAX).is_none());
+        let tilted = Disk::new(Vec3::zero(), Vec3::new(1.0, 1.0, 0.0), 1.0, grey());
+        check_hits(&tilted, |p| {
+            p.x() + p.y() < 1e-4 && p.length() <= 1.0 + 1e-4
+        });
+    }
+
+    #[test]
+    fn test_cylinder() {
+        let cylinder = Cylinder::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.5,
+            grey(),
+        );
+        let r = Ray::new(Vec3::new(0.0, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = cylinder.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((hit.t() - 4.5).abs() < 1e-5);
+        assert_near(&Vec3::new(0.0, 0.0, 1.0), hit.normal(), 1e-4);
+        assert!((hit.v() - 0.75).abs() < 1e-5);
+        // Down onto the top cap.
+        let r = Ray::new(Vec3::new(0.2, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = cylinder.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((hit.t() - 2.0).abs() < 1e-5);
+        assert_near(&Vec3::new(0.0, 1.0, 0.0), hit.normal(), 1e-4);
+        // Past the end.
+        let r = Ray::new(Vec3::new(0.0, 1.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cylinder.hit(&r, 0.001, f32::MAX).is_none());
+
+        let axis = Vec3::new(1.0, 2.0, -1.0).unit_vector();
+        let tilted = Cylinder::new(-axis, axis, 0.5, grey());
+        let hits = check_hits(&tilted, |p| {
+            let along = Vec3::dot(p, &axis);
+            let radial = (*p - along * axis).length();
+            along.abs() <= 1.0 + 1e-4
+                && ((radial - 0.5).abs() < 1e-4
+                    || ((along.abs() - 1.0).abs() < 1e-4 && radial <= 0.5 + 1e-4))
+        });
+        assert!(hits > 100);
+    }
+
+    // Rises with v.
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value(&self, _: f32, v: f32, _: &Vec3) -> Vec3 {
+            Vec3::new(v, v, v)
+        }
+    }
+
+    #[test]
+    fn test_bumped_cylinder() {
+        let cylinder = Cylinder::new(
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.5,
+            grey(),
+        );
+        // Rising by 0.5 over the 2 units of its height.
+        let bumped = Bumped::bump(cylinder, Box::new(Ramp), 0.5);
+        let r = Ray::new(Vec3::new(0.0, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = bumped.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_near(&Vec3::new(0.0, 0.0, 1.0), hit.normal(), 1e-4);
+        assert_near(
+            &Vec3::new(0.0, -0.25, 1.0).unit_vector(),
+            hit.shading_normal(),
+            1e-3,
+        );
+    }
+
+    #[test]
+    fn test_cone() {
+        let cone = Cone::new(Vec3::zero(), Vec3::new(0.0, 2.0, 0.0), 1.0, grey());
+        // Halfway up the radius is a half.
+        let r = Ray::new(Vec3::new(0.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = cone.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((hit.t() - 4.5).abs() < 1e-5);
+        assert_near(&Vec3::new(0.0, 1.0, 2.0).unit_vector(), hit.normal(), 1e-4);
+        // Up into the base.
+        let r = Ray::new(Vec3::new(0.5, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let hit = cone.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(1.0, hit.t());
+        assert_near(&Vec3::new(0.0, -1.0, 0.0), hit.normal(), 1e-4);
+        // Not the mirror cone above the apex.
+        let r = Ray::new(Vec3::new(0.0, 3.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cone.hit(&r, 0.001, f32::MAX).is_none());
+
+        let hits = check_hits(&cone, |p| {
+            let radial = (p.x() * p.x() + p.z() * p.z()).sqrt();
+            (-1e-4..=2.0 + 1e-4).contains(&p.y())
+                && ((radial - (2.0 - p.y()) / 2.0).abs() < 1e-4
+                    || (p.y().abs() < 1e-4 && radial <= 1.0 + 1e-4))
+        });
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn test_torus() {
+        let torus = Torus::new(Vec3::zero(), Vec3::new(0.0, 1.0, 0.0), 1.0, 0.25, grey());
+        // Through the hole, then along the rim into the tube.
+        let r = Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(torus.hit(&r, 0.001, f32::MAX).is_none());
+        let r = Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = torus.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((hit.t() - 3.75).abs() < 1e-4);
+        assert_near(&Vec3::new(1.0, 0.0, 0.0), hit.normal(), 1e-4);
+        let hit = torus.hit(&r, 4.0, f32::MAX).unwrap();
+        assert!((hit.t() - 4.25).abs() < 1e-4);
+        assert_near(&Vec3::new(-1.0, 0.0, 0.0), hit.normal(), 1e-4);
+        let r = Ray::new(Vec3::new(1.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = torus.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((hit.t() - 4.75).abs() < 1e-4);
+        assert_near(&Vec3::new(0.0, 1.0, 0.0), hit.normal(), 1e-4);
+
+        let axis = Vec3::new(1.0, 1.0, 1.0).unit_vector();
+        let tilted = Torus::new(Vec3::zero(), axis, 1.0, 0.25, grey());
+        let hits = check_hits(&tilted, |p| {
+            let along = Vec3::dot(p, &axis);
+            let radial = (*p - along * axis).length();
+            ((radial - 1.0).powi(2) + along * along).sqrt() - 0.25 < 1e-3
+        });
+        assert!(hits > 100);
+    }
+
+    #[test]
+    fn test_quartic() {
+        // (x - 1)(x + 2)(x - 3)(x - 0.5)
+        let mut roots = solve_quartic(&[1.0, -2.5, -4.0, 8.5, -3.0]);
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let expected = [-2.0, 0.5, 1.0, 3.0];
+        assert_eq!(4, roots.len());
+        for (a, b) in roots.iter().zip(expected.iter()) {
+            assert!((a - b).abs() < 1e-9, "{:?}", roots);
+        }
+        assert!(solve_quartic(&[1.0, 0.0, 0.0, 0.0, 1.0]).is_empty());
+    }
+}
@@ -1,11 +1,37 @@
 use crate::vec3::Vec3;
 
+// Origin, direction, the time at which the ray was sent and, for rays that
+// have only met mirrors and glass since leaving the camera, its differentials.
 #[derive(Copy, Clone, Debug)]
-pub struct Ray(Vec3, Vec3);
+pub struct Ray(Vec3, Vec3, f32, Option<Differentials>);
+
+// The rays through the neighbouring pixels to the right and above, which
+// give the footprint of a pixel wherever the ray lands.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Differentials {
+    pub rx_origin: Vec3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Vec3,
+    pub ry_direction: Vec3,
+}
 
 impl Ray {
     pub fn new(origin: Vec3, direction: Vec3) -> Self {
-        Ray(origin, direction)
+        Ray(origin, direction, 0.0, None)
+    }
+
+    pub fn with_time(mut self, time: f32) -> Self {
+        self.2 = time;
+        self
+    }
+
+    pub fn with_differentials(mut self, differentials: Differentials) -> Self {
+        self.3 = Some(differentials);
+        self
+    }
+
+    pub fn differentials(&self) -> Option<&Differentials> {
+        self.3.as_ref()
     }
 
     pub fn origin(&self) -> &Vec3 {
@@ -16,6 +42,10 @@ impl Ray {
         &self.1
     }
 
+    pub fn time(&self) -> f32 {
+        self.2
+    }
+
     pub fn point_at_parameter(&self, t: f32) -> Vec3 {
         self.0 + t * self.1
     }
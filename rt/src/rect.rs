@@ -0,0 +1,272 @@
+use crate::{
+    aabb::Aabb,
+    hitable::{HitRecord, Hitable},
+    material::Material,
+    ray::Ray,
+    vec3::Vec3,
+};
+
+// Rects have no thickness; their boxes get a little so the slab test works.
+const THICKNESS: f32 = 1e-4;
+
+// A rectangle perpendicular to `axis` at `k`, spanning [a0, a1] x [b0, b1]
+// along the next two axes in xyz order (so x and y for z, x and z for y).
+struct AxisRect {
+    axis: usize,
+    a0: f32,
+    a1: f32,
+    b0: f32,
+    b1: f32,
+    k: f32,
+}
+
+impl AxisRect {
+    // t and the position within the rect, both in [0, 1].
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32, f32)> {
+        let (a, b) = other_axes(self.axis);
+        let t = (self.k - r.origin()[self.axis]) / r.direction()[self.axis];
+        if !(t > t_min && t < t_max) {
+            return None;
+        }
+        let pa = r.origin()[a] + t * r.direction()[a];
+        let pb = r.origin()[b] + t * r.direction()[b];
+        if pa < self.a0 || pa > self.a1 || pb < self.b0 || pb > self.b1 {
+            return None;
+        }
+        Some((
+            t,
+            (pa - self.a0) / (self.a1 - self.a0),
+            (pb - self.b0) / (self.b1 - self.b0),
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let (a, b) = other_axes(self.axis);
+        let mut min = Vec3::zero();
+        let mut max = Vec3::zero();
+        min[self.axis] = self.k - THICKNESS;
+        max[self.axis] = self.k + THICKNESS;
+        min[a] = self.a0;
+        max[a] = self.a1;
+        min[b] = self.b0;
+        max[b] = self.b1;
+        Aabb::new(min, max)
+    }
+
+    // How a point moves with the position within the rect.
+    fn derivatives(&self) -> (Vec3, Vec3) {
+        let (a, b) = other_axes(self.axis);
+        let mut dpdu = Vec3::zero();
+        let mut dpdv = Vec3::zero();
+        dpdu[a] = self.a1 - self.a0;
+        dpdv[b] = self.b1 - self.b0;
+        (dpdu, dpdv)
+    }
+
+    fn normal(&self, sign: f32) -> Vec3 {
+        let mut normal = Vec3::zero();
+        normal[self.axis] = sign;
+        normal
+    }
+}
+
+fn other_axes(axis: usize) -> (usize, usize) {
+    match axis {
+        0 => (1, 2),
+        1 => (0, 2),
+        _ => (0, 1),
+    }
+}
+
+macro_rules! impl_rect {
+    ($name:ident, $axis:expr, $a:ident, $b:ident, $k:ident) => {
+        // Faces the positive side of its axis; see `FlipNormals`.
+        pub struct $name {
+            rect: AxisRect,
+            material: Box<dyn Material>,
+        }
+
+        impl $name {
+            pub fn new(
+                $a: (f32, f32),
+                $b: (f32, f32),
+                $k: f32,
+                material: Box<dyn Material>,
+            ) -> Self {
+                $name {
+                    rect: AxisRect {
+                        axis: $axis,
+                        a0: $a.0,
+                        a1: $a.1,
+                        b0: $b.0,
+                        b1: $b.1,
+                        k: $k,
+                    },
+                    material,
+                }
+            }
+        }
+
+        impl Hitable for $name {
+            fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+                let (t, u, v) = self.rect.hit(r, t_min, t_max)?;
+                let (dpdu, dpdv) = self.rect.derivatives();
+                Some(
+                    HitRecord::new(
+                        t,
+                        r.point_at_parameter(t),
+                        self.rect.normal(1.0),
+                        self.material.as_ref(),
+                    )
+                    .with_uv(u, v)
+                    .with_derivatives(dpdu, dpdv),
+                )
+            }
+
+            fn bounding_box(&self) -> Option<Aabb> {
+                Some(self.rect.bounding_box())
+            }
+        }
+    };
+}
+
+impl_rect!(XyRect, 2, x, y, z);
+impl_rect!(XzRect, 1, x, z, y);
+impl_rect!(YzRect, 0, y, z, x);
+
+// An axis aligned box with outward facing normals. Named so as not to clash
+// with `std::boxed::Box`.
+pub struct Cuboid {
+    min: Vec3,
+    max: Vec3,
+    // Each face with the sign of its normal.
+    faces: [(AxisRect, f32); 6],
+    material: Box<dyn Material>,
+}
+
+impl Cuboid {
+    pub fn new(p0: Vec3, p1: Vec3, material: Box<dyn Material>) -> Self {
+        let min = Vec3::new(p0.x().min(p1.x()), p0.y().min(p1.y()), p0.z().min(p1.z()));
+        let max = Vec3::new(p0.x().max(p1.x()), p0.y().max(p1.y()), p0.z().max(p1.z()));
+        let face = |axis: usize, k: f32| {
+            let (a, b) = other_axes(axis);
+            AxisRect {
+                axis,
+                a0: min[a],
+                a1: max[a],
+                b0: min[b],
+                b1: max[b],
+                k,
+            }
+        };
+        Cuboid {
+            min,
+            max,
+            faces: [
+                (face(0, min.x()), -1.0),
+                (face(0, max.x()), 1.0),
+                (face(1, min.y()), -1.0),
+                (face(1, max.y()), 1.0),
+                (face(2, min.z()), -1.0),
+                (face(2, max.z()), 1.0),
+            ],
+            material,
+        }
+    }
+}
+
+impl Hitable for Cuboid {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let mut closest = t_max;
+        let mut hit = None;
+        for (rect, sign) in self.faces.iter() {
+            if let Some((t, u, v)) = rect.hit(r, t_min, closest) {
+                closest = t;
+                let (dpdu, dpdv) = rect.derivatives();
+                hit = Some(
+                    HitRecord::new(
+                        t,
+                        r.point_at_parameter(t),
+                        rect.normal(*sign),
+                        self.material.as_ref(),
+                    )
+                    .with_uv(u, v)
+                    .with_derivatives(dpdu, dpdv),
+                );
+            }
+        }
+        hit
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.min, self.max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cuboid, XyRect, XzRect, YzRect};
+    use crate::{
+        hitable::{FlipNormals, Hitable},
+        ray::Ray,
+        test_support::grey,
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_rects() {
+        let down = Ray::new(Vec3::new(1.5, 5.0, 2.5), Vec3::new(0.0, -1.0, 0.0));
+        let xz = XzRect::new((1.0, 2.0), (2.0, 4.0), 3.0, grey());
+        let hit = xz.hit(&down, 0.001, f32::MAX).unwrap();
+        assert_eq!(2.0, hit.t());
+        assert_eq!(&Vec3::new(0.0, 1.0, 0.0), hit.normal());
+        assert_eq!((0.5, 0.25), (hit.u(), hit.v()));
+        assert!(xz.bounding_box().unwrap().hit(&down, 0.001, f32::MAX));
+        let flipped = FlipNormals(xz);
+        assert_eq!(
+            &Vec3::new(0.0, -1.0, 0.0),
+            flipped.hit(&down, 0.001, f32::MAX).unwrap().normal()
+        );
+        assert!(flipped.hit(&down, 0.001, 1.5).is_none());
+
+        let xy = XyRect::new((0.0, 1.0), (0.0, 1.0), -1.0, grey());
+        let r = Ray::new(Vec3::new(0.5, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(
+            &Vec3::new(0.0, 0.0, 1.0),
+            xy.hit(&r, 0.001, f32::MAX).unwrap().normal()
+        );
+        let r = Ray::new(Vec3::new(1.5, 0.5, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(xy.hit(&r, 0.001, f32::MAX).is_none());
+
+        let yz = YzRect::new((0.0, 1.0), (0.0, 1.0), 2.0, grey());
+        let r = Ray::new(Vec3::new(0.0, 0.5, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(2.0, yz.hit(&r, 0.001, f32::MAX).unwrap().t());
+        // Parallel to the rect.
+        let r = Ray::new(Vec3::new(0.0, 0.5, 0.5), Vec3::new(0.0, 1.0, 0.0));
+        assert!(yz.hit(&r, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn test_cuboid() {
+        let cuboid = Cuboid::new(
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(-1.0, -1.0, -2.0),
+            grey(),
+        );
+        let r = Ray::new(Vec3::new(0.5, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = cuboid.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(4.0, hit.t());
+        assert_eq!(&Vec3::new(0.0, 0.0, 1.0), hit.normal());
+        // From the inside the far face is hit, still facing out.
+        let r = Ray::new(Vec3::zero(), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = cuboid.hit(&r, 0.001, f32::MAX).unwrap();
+        assert_eq!(1.0, hit.t());
+        assert_eq!(&Vec3::new(-1.0, 0.0, 0.0), hit.normal());
+        let r = Ray::new(Vec3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cuboid.hit(&r, 0.001, f32::MAX).is_none());
+        assert_eq!(
+            Vec3::new(-1.0, -1.0, -2.0),
+            *cuboid.bounding_box().unwrap().min()
+        );
+    }
+}
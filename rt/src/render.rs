@@ -0,0 +1,386 @@
+use crate::{
+    camera::Camera, hitable::HitRecord, image::Image, ray::Ray, sampler::Sampler, scene::Scene,
+    tonemap::ToneMap, vec3::Vec3,
+};
+use rand::{thread_rng, Rng};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+const MAX_DEPTH: u32 = 50;
+const TILE_SIZE: usize = 16;
+
+pub struct Renderer {
+    width: usize,
+    height: usize,
+    samples: usize,
+    threads: usize,
+    seed: u64,
+    tone_map: ToneMap,
+}
+
+impl Renderer {
+    pub fn new(width: usize, height: usize, samples: usize) -> Self {
+        Renderer {
+            width,
+            height,
+            samples,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: thread_rng().gen(),
+            tone_map: ToneMap::default(),
+        }
+    }
+
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    // Every sample of every pixel draws from its own generator derived from the
+    // seed, so the output does not depend on the thread count or tile order.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // Carried by the rendered image and used when it is saved as PPM or PNG.
+    pub fn with_tone_map(mut self, tone_map: ToneMap) -> Self {
+        self.tone_map = tone_map;
+        self
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn tone_map(&self) -> &ToneMap {
+        &self.tone_map
+    }
+
+    pub fn render(&self, scene: &Scene, camera: &Camera) -> Image {
+        let camera = &camera.with_resolution(self.width, self.height);
+        let tiles_x = self.width.div_ceil(TILE_SIZE);
+        let tiles_y = self.height.div_ceil(TILE_SIZE);
+        let next_tile = AtomicUsize::new(0);
+        let mut image = Image::new(self.width, self.height);
+        image.set_tone_map(self.tone_map);
+        let done: Vec<Vec<(usize, Vec<Vec3>)>> = thread::scope(|s| {
+            let workers: Vec<_> = (0..self.threads)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut done = vec![];
+                        loop {
+                            let tile = next_tile.fetch_add(1, Ordering::Relaxed);
+                            if tile >= tiles_x * tiles_y {
+                                break done;
+                            }
+                            done.push((
+                                tile,
+                                self.render_tile(scene, camera, tile % tiles_x, tile / tiles_x),
+                            ));
+                        }
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).collect()
+        });
+        for (tile, tile_pixels) in done.into_iter().flatten() {
+            let (x0, y0) = ((tile % tiles_x) * TILE_SIZE, (tile / tiles_x) * TILE_SIZE);
+            let tile_width = TILE_SIZE.min(self.width - x0);
+            for (row, line) in tile_pixels.chunks(tile_width).enumerate() {
+                let start = (y0 + row) * self.width + x0;
+                image.pixels_mut()[start..start + tile_width].copy_from_slice(line);
+            }
+        }
+        image
+    }
+
+    fn render_tile(
+        &self,
+        scene: &Scene,
+        camera: &Camera,
+        tile_x: usize,
+        tile_y: usize,
+    ) -> Vec<Vec3> {
+        let (x0, y0) = (tile_x * TILE_SIZE, tile_y * TILE_SIZE);
+        let (x1, y1) = (
+            (x0 + TILE_SIZE).min(self.width),
+            (y0 + TILE_SIZE).min(self.height),
+        );
+        let mut pixels = Vec::with_capacity((x1 - x0) * (y1 - y0));
+        for y in y0..y1 {
+            let j = self.height - 1 - y;
+            for i in x0..x1 {
+                let pixel = (y * self.width + i) as u64;
+                let mut col = Vec3::zero();
+                for sample in 0..self.samples {
+                    let mut sampler = Sampler::for_sample(self.seed, pixel, sample as u64);
+                    let u = (i as f32 + sampler.gen::<f32>()) / self.width as f32;
+                    let v = (j as f32 + sampler.gen::<f32>()) / self.height as f32;
+                    let r = camera.get_ray(u, v, &mut sampler);
+                    col += color(&r, scene, 0, &mut sampler);
+                }
+                col /= self.samples as f32;
+                pixels.push(col);
+            }
+        }
+        pixels
+    }
+}
+
+pub fn color(r: &Ray, scene: &Scene, depth: u32, sampler: &mut Sampler) -> Vec3 {
+    trace(r, scene, depth, sampler, None)
+}
+
+// `scatter_pdf` is the density with which a diffuse bounce picked `r`; when it
+// reaches the environment that light was also sampled directly, so the two
+// estimates are combined with multiple importance sampling.
+fn trace(
+    r: &Ray,
+    scene: &Scene,
+    depth: u32,
+    sampler: &mut Sampler,
+    scatter_pdf: Option<f32>,
+) -> Vec3 {
+    if let Some(hit) = scene.world().hit(r, 0.001, f32::MAX) {
+        let material = hit.material();
+        let emitted = material.emitted(r, &hit);
+        if depth >= MAX_DEPTH {
+            return emitted;
+        }
+        let direct = sample_environment(r, &hit, scene, sampler);
+        if let Some((attenuation, scattered)) = material.scatter(r, &hit, sampler) {
+            let pdf = material
+                .eval(r, &hit, scattered.direction())
+                .map(|(_, pdf)| pdf);
+            return emitted
+                + direct
+                + attenuation * trace(&scattered, scene, depth + 1, sampler, pdf);
+        }
+        emitted + direct
+    } else {
+        let environment = scene.environment();
+        let radiance = environment.color(r.direction());
+        match scatter_pdf {
+            Some(pdf) => radiance * power_heuristic(pdf, environment.pdf(r.direction())),
+            None => radiance,
+        }
+    }
+}
+
+fn sample_environment(r: &Ray, hit: &HitRecord, scene: &Scene, sampler: &mut Sampler) -> Vec3 {
+    let (dir, radiance, light_pdf) = match scene.environment().sample(sampler) {
+        Some(sample) => sample,
+        None => return Vec3::zero(),
+    };
+    let (f, scatter_pdf) = match hit.material().eval(r, hit, &dir) {
+        Some(eval) => eval,
+        None => return Vec3::zero(),
+    };
+    if light_pdf <= 0.0 || f == Vec3::zero() {
+        return Vec3::zero();
+    }
+    let shadow = Ray::new(*hit.p(), dir).with_time(r.time());
+    if scene.world().hit(&shadow, 0.001, f32::MAX).is_some() {
+        return Vec3::zero();
+    }
+    f * radiance * power_heuristic(light_pdf, scatter_pdf) / light_pdf
+}
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Renderer;
+    use crate::{
+        camera::Camera,
+        environment::{ConstantColor, Equirectangular, Gradient},
+        hitable::{HitableList, MovingSphere, Sphere},
+        image::Image,
+        material::{Dielectric, DiffuseLight},
+        scene::{cornell_box, Scene},
+        test_support::grey,
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_render_empty_world() {
+        let scene = Scene::new(Box::new(HitableList::new()), Box::new(Gradient::sky()));
+        let camera = Camera::new(
+            Vec3::zero(),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            2.0,
+            0.0,
+            1.0,
+        );
+        let image = Renderer::new(4, 2, 1).render(&scene, &camera);
+        assert_eq!((4, 2), (image.width(), image.height()));
+        let pixels = image.pixels();
+        for col in pixels.iter() {
+            assert!(col.b() >= col.r());
+        }
+        assert!(pixels[0].r() < pixels[7].r());
+    }
+
+    #[test]
+    fn test_seeded_render_ignores_thread_count() {
+        let mut world = HitableList::new();
+        world.push(Box::new(Sphere::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            0.5,
+            grey(),
+        )));
+        world.push(Box::new(Sphere::new(
+            Vec3::new(1.0, 0.0, -1.0),
+            0.5,
+            Box::new(Dielectric::new(1.5)),
+        )));
+        let scene = Scene::new(Box::new(world), Box::new(Gradient::sky()));
+        let camera = Camera::new(
+            Vec3::new(0.0, 0.5, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            60.0,
+            1.5,
+            0.1,
+            2.0,
+        );
+        let single = Renderer::new(37, 21, 4)
+            .with_seed(7)
+            .with_threads(1)
+            .render(&scene, &camera);
+        let multi = Renderer::new(37, 21, 4)
+            .with_seed(7)
+            .with_threads(5)
+            .render(&scene, &camera);
+        assert_eq!(single, multi);
+        let other = Renderer::new(37, 21, 4)
+            .with_seed(8)
+            .with_threads(5)
+            .render(&scene, &camera);
+        assert_ne!(single, other);
+    }
+
+    #[test]
+    fn test_render_inside_light() {
+        let mut world = HitableList::new();
+        world.push(Box::new(Sphere::new(
+            Vec3::zero(),
+            10.0,
+            Box::new(DiffuseLight::new(Vec3::new(4.0, 2.0, 1.0))),
+        )));
+        let scene = Scene::new(Box::new(world), Box::new(Gradient::sky()));
+        let camera = Camera::new(
+            Vec3::zero(),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            90.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let image = Renderer::new(3, 3, 2).render(&scene, &camera);
+        for col in image.pixels().iter() {
+            assert_eq!(&Vec3::new(4.0, 2.0, 1.0), col);
+        }
+    }
+
+    #[test]
+    fn test_furnace() {
+        // A diffuse sphere under uniform light reflects exactly its albedo,
+        // whether the light is found by scattering or by sampling the map.
+        let mut world = HitableList::new();
+        world.push(Box::new(Sphere::new(Vec3::zero(), 1.0, grey())));
+        let map =
+            Equirectangular::new(Image::from_pixels(8, 4, vec![Vec3::new(1.0, 1.0, 1.0); 32]));
+        let scene = Scene::new(Box::new(world), Box::new(map));
+        let camera = Camera::new(
+            Vec3::new(0.0, 0.0, 4.0),
+            Vec3::zero(),
+            Vec3::new(0.0, 1.0, 0.0),
+            10.0,
+            1.0,
+            0.0,
+            1.0,
+        );
+        let image = Renderer::new(2, 2, 1000)
+            .with_seed(3)
+            .render(&scene, &camera);
+        for col in image.pixels().iter() {
+            assert!((col.r() - 0.5).abs() < 0.02, "{:?}", col);
+        }
+    }
+
+    #[test]
+    fn test_cornell_box() {
+        let (scene, camera) = cornell_box(1.0);
+        let image = Renderer::new(8, 8, 64).with_seed(1).render(&scene, &camera);
+        let column = |x| (0..8).fold(Vec3::zero(), |acc, y| acc + image.pixel(x, y));
+        // Light bounced off the green wall on the left and the red one on the
+        // right; the outermost columns look past the room.
+        let (left, right) = (column(1), column(6));
+        assert!(left.g() > left.r(), "{:?}", left);
+        assert!(right.r() > right.g(), "{:?}", right);
+    }
+
+    #[test]
+    fn test_motion_blur() {
+        // A white sphere crossing the view while the shutter is open leaves a
+        // grey smear on the black background.
+        let mut world = HitableList::new();
+        world.push(Box::new(MovingSphere::new(
+            Vec3::new(-2.0, 0.0, -5.0),
+            Vec3::new(2.0, 0.0, -5.0),
+            0.0,
+            1.0,
+            1.0,
+            Box::new(DiffuseLight::new(Vec3::new(1.0, 1.0, 1.0))),
+        )));
+        let scene = Scene::new(Box::new(world), Box::new(ConstantColor::new(Vec3::zero())));
+        let camera = Camera::new(
+            Vec3::zero(),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            10.0,
+            1.0,
+            0.0,
+            1.0,
+        )
+        .with_shutter(0.0, 1.0);
+        let image = Renderer::new(1, 1, 400)
+            .with_seed(4)
+            .render(&scene, &camera);
+        let col = image.pixel(0, 0);
+        assert!(col.r() > 0.3 && col.r() < 0.7, "{:?}", col);
+        // Caught at the start of its path it is clear of the whole pixel,
+        // which spans 5 tan(5 degrees) either side of the middle.
+        let still = Renderer::new(1, 1, 4)
+            .with_seed(4)
+            .render(&scene, &camera.with_shutter(0.0, 0.0));
+        assert_eq!(Vec3::zero(), still.pixel(0, 0));
+    }
+}
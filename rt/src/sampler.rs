@@ -0,0 +1,68 @@
+use rand::{Error, RngCore, SeedableRng};
+use rand_pcg::Pcg32;
+
+// PCG is used because its output is specified independently of the platform,
+// so a seeded render comes out the same on every machine.
+pub struct Sampler(Pcg32);
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Sampler(Pcg32::seed_from_u64(seed))
+    }
+
+    // Each sample of each pixel gets its own sequence, so the result does not
+    // depend on the order in which pixels are rendered.
+    pub fn for_sample(seed: u64, pixel: u64, sample: u64) -> Self {
+        Sampler::new(mix(mix(seed, pixel), sample))
+    }
+}
+
+impl RngCore for Sampler {
+    fn next_u32(&mut self) -> u32 {
+        self.0.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.0.try_fill_bytes(dest)
+    }
+}
+
+pub(crate) fn mix(seed: u64, stream: u64) -> u64 {
+    // splitmix64 finalizer
+    let mut z = seed ^ stream.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sampler;
+    use rand::RngCore;
+
+    #[test]
+    fn test_sampler_is_reproducible() {
+        let mut sampler = Sampler::new(42);
+        let values: Vec<u32> = (0..4).map(|_| sampler.next_u32()).collect();
+        assert_eq!(vec![3400036912, 3349765444, 515166382, 4224707908], values);
+    }
+
+    #[test]
+    fn test_samples_are_independent() {
+        let mut a = Sampler::for_sample(1, 10, 0);
+        let mut b = Sampler::for_sample(1, 10, 1);
+        let mut c = Sampler::for_sample(1, 11, 0);
+        let (a, b, c) = (a.next_u64(), b.next_u64(), c.next_u64());
+        assert_ne!(a, b);
+        assert_ne!(a, c);
+        assert_eq!(a, Sampler::for_sample(1, 10, 0).next_u64());
+    }
+}
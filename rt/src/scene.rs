@@ -0,0 +1,96 @@
+use crate::{
+    camera::Camera,
+    environment::{ConstantColor, Environment},
+    hitable::{FlipNormals, Hitable, HitableList},
+    material::{DiffuseLight, Lambertian},
+    rect::{Cuboid, XyRect, XzRect, YzRect},
+    transform::{Transform, Transformed},
+    vec3::Vec3,
+};
+
+pub struct Scene {
+    world: Box<dyn Hitable>,
+    environment: Box<dyn Environment>,
+}
+
+impl Scene {
+    pub fn new(world: Box<dyn Hitable>, environment: Box<dyn Environment>) -> Self {
+        Scene { world, environment }
+    }
+
+    pub fn world(&self) -> &dyn Hitable {
+        self.world.as_ref()
+    }
+
+    pub fn environment(&self) -> &dyn Environment {
+        self.environment.as_ref()
+    }
+}
+
+// The Cornell box as set up in "Ray Tracing: The Next Week": a 555 unit room
+// with a red and a green wall, a ceiling light and two turned white boxes,
+// seen through the open side.
+pub fn cornell_box(aspect: f32) -> (Scene, Camera) {
+    let red = || Box::new(Lambertian::new(Vec3::new(0.65, 0.05, 0.05)));
+    let white = || Box::new(Lambertian::new(Vec3::new(0.73, 0.73, 0.73)));
+    let green = || Box::new(Lambertian::new(Vec3::new(0.12, 0.45, 0.15)));
+    let light = Box::new(DiffuseLight::new(Vec3::new(15.0, 15.0, 15.0)));
+
+    let mut world = HitableList::new();
+    world.push(Box::new(FlipNormals(YzRect::new(
+        (0.0, 555.0),
+        (0.0, 555.0),
+        555.0,
+        green(),
+    ))));
+    world.push(Box::new(YzRect::new(
+        (0.0, 555.0),
+        (0.0, 555.0),
+        0.0,
+        red(),
+    )));
+    world.push(Box::new(FlipNormals(XzRect::new(
+        (213.0, 343.0),
+        (227.0, 332.0),
+        554.0,
+        light,
+    ))));
+    world.push(Box::new(FlipNormals(XzRect::new(
+        (0.0, 555.0),
+        (0.0, 555.0),
+        555.0,
+        white(),
+    ))));
+    world.push(Box::new(XzRect::new(
+        (0.0, 555.0),
+        (0.0, 555.0),
+        0.0,
+        white(),
+    )));
+    world.push(Box::new(FlipNormals(XyRect::new(
+        (0.0, 555.0),
+        (0.0, 555.0),
+        555.0,
+        white(),
+    ))));
+    world.push(Box::new(Transformed::new(
+        Cuboid::new(Vec3::zero(), Vec3::new(165.0, 330.0, 165.0), white()),
+        Transform::translate(Vec3::new(265.0, 0.0, 295.0)) * Transform::rotate_y(15.0),
+    )));
+    world.push(Box::new(Transformed::new(
+        Cuboid::new(Vec3::zero(), Vec3::new(165.0, 165.0, 165.0), white()),
+        Transform::translate(Vec3::new(130.0, 0.0, 65.0)) * Transform::rotate_y(-18.0),
+    )));
+
+    let camera = Camera::new(
+        Vec3::new(278.0, 278.0, -800.0),
+        Vec3::new(278.0, 278.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        aspect,
+        0.0,
+        10.0,
+    );
+    let scene = Scene::new(Box::new(world), Box::new(ConstantColor::new(Vec3::zero())));
+    (scene, camera)
+}
@@ -0,0 +1,454 @@
+use crate::{
+    aabb::Aabb,
+    hitable::{HitRecord, Hitable},
+    material::Material,
+    ray::Ray,
+    vec3::Vec3,
+};
+
+// A signed distance field: negative inside, positive outside, and never more
+// than the distance to the surface so that it is safe to step by.
+pub trait Sdf: Send + Sync {
+    fn distance(&self, p: &Vec3) -> f32;
+
+    // None for fields that go on forever, such as repetitions.
+    fn bounding_box(&self) -> Option<Aabb>;
+}
+
+impl<S: Sdf + ?Sized> Sdf for Box<S> {
+    fn distance(&self, p: &Vec3) -> f32 {
+        (**self).distance(p)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        (**self).bounding_box()
+    }
+}
+
+pub struct SphereSdf {
+    center: Vec3,
+    radius: f32,
+}
+
+impl SphereSdf {
+    pub fn new(center: Vec3, radius: f32) -> Self {
+        SphereSdf { center, radius }
+    }
+}
+
+impl Sdf for SphereSdf {
+    fn distance(&self, p: &Vec3) -> f32 {
+        (*p - self.center).length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.center, self.center).padded(self.radius))
+    }
+}
+
+// An axis aligned box reaching `half_size` from its center on every axis.
+pub struct BoxSdf {
+    center: Vec3,
+    half_size: Vec3,
+}
+
+impl BoxSdf {
+    pub fn new(center: Vec3, half_size: Vec3) -> Self {
+        BoxSdf { center, half_size }
+    }
+}
+
+impl Sdf for BoxSdf {
+    fn distance(&self, p: &Vec3) -> f32 {
+        box_distance(&(*p - self.center), &self.half_size)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(
+            self.center - self.half_size,
+            self.center + self.half_size,
+        ))
+    }
+}
+
+// A box of the same size as `BoxSdf` with its edges and corners rounded off
+// by `radius`.
+pub struct RoundedBoxSdf {
+    center: Vec3,
+    half_size: Vec3,
+    radius: f32,
+}
+
+impl RoundedBoxSdf {
+    pub fn new(center: Vec3, half_size: Vec3, radius: f32) -> Self {
+        RoundedBoxSdf {
+            center,
+            half_size,
+            radius,
+        }
+    }
+}
+
+impl Sdf for RoundedBoxSdf {
+    fn distance(&self, p: &Vec3) -> f32 {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        box_distance(&(*p - self.center), &(self.half_size - r)) - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::new(
+            self.center - self.half_size,
+            self.center + self.half_size,
+        ))
+    }
+}
+
+fn box_distance(p: &Vec3, half_size: &Vec3) -> f32 {
+    let mut q = Vec3::zero();
+    for i in 0..3 {
+        q[i] = p[i].abs() - half_size[i];
+    }
+    let outside = Vec3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0));
+    outside.length() + q.x().max(q.y()).max(q.z()).min(0.0)
+}
+
+// The points within `radius` of the segment from `a` to `b`.
+pub struct CapsuleSdf {
+    a: Vec3,
+    b: Vec3,
+    radius: f32,
+}
+
+impl CapsuleSdf {
+    pub fn new(a: Vec3, b: Vec3, radius: f32) -> Self {
+        CapsuleSdf { a, b, radius }
+    }
+}
+
+impl Sdf for CapsuleSdf {
+    fn distance(&self, p: &Vec3) -> f32 {
+        let pa = *p - self.a;
+        let ba = self.b - self.a;
+        let h = (Vec3::dot(&pa, &ba) / ba.squared_length()).clamp(0.0, 1.0);
+        (pa - h * ba).length() - self.radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb::surrounding(
+            &Aabb::new(self.a, self.a).padded(self.radius),
+            &Aabb::new(self.b, self.b).padded(self.radius),
+        ))
+    }
+}
+
+// A ring lying flat in the xz plane around `center`.
+pub struct TorusSdf {
+    center: Vec3,
+    major_radius: f32,
+    minor_radius: f32,
+}
+
+impl TorusSdf {
+    pub fn new(center: Vec3, major_radius: f32, minor_radius: f32) -> Self {
+        TorusSdf {
+            center,
+            major_radius,
+            minor_radius,
+        }
+    }
+}
+
+impl Sdf for TorusSdf {
+    fn distance(&self, p: &Vec3) -> f32 {
+        let p = *p - self.center;
+        let ring = (p.x() * p.x() + p.z() * p.z()).sqrt() - self.major_radius;
+        (ring * ring + p.y() * p.y()).sqrt() - self.minor_radius
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let extent = Vec3::new(outer, self.minor_radius, outer);
+        Some(Aabb::new(self.center - extent, self.center + extent))
+    }
+}
+
+// Blends two fields where they come within `k` of each other, filling in
+// the crease between them.
+pub struct SmoothUnion<A, B> {
+    a: A,
+    b: B,
+    k: f32,
+}
+
+impl<A: Sdf, B: Sdf> SmoothUnion<A, B> {
+    pub fn new(a: A, b: B, k: f32) -> Self {
+        SmoothUnion { a, b, k }
+    }
+}
+
+impl<A: Sdf, B: Sdf> Sdf for SmoothUnion<A, B> {
+    fn distance(&self, p: &Vec3) -> f32 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 + 0.5 * (b - a) / self.k).clamp(0.0, 1.0);
+        b + h * (a - b) - self.k * h * (1.0 - h)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        // The blend swells the union by at most a quarter of k.
+        let a = self.a.bounding_box()?;
+        let b = self.b.bounding_box()?;
+        Some(Aabb::surrounding(&a, &b).padded(0.25 * self.k))
+    }
+}
+
+// Carves `b` out of `a`, rounding the cut over `k`.
+pub struct SmoothSubtraction<A, B> {
+    a: A,
+    b: B,
+    k: f32,
+}
+
+impl<A: Sdf, B: Sdf> SmoothSubtraction<A, B> {
+    pub fn new(a: A, b: B, k: f32) -> Self {
+        SmoothSubtraction { a, b, k }
+    }
+}
+
+impl<A: Sdf, B: Sdf> Sdf for SmoothSubtraction<A, B> {
+    fn distance(&self, p: &Vec3) -> f32 {
+        let (a, b) = (self.a.distance(p), self.b.distance(p));
+        let h = (0.5 - 0.5 * (a + b) / self.k).clamp(0.0, 1.0);
+        a + h * (-b - a) + self.k * h * (1.0 - h)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.a.bounding_box()
+    }
+}
+
+// Copies of a field, which should fit in the cell around the origin, every
+// `period` along each axis; a zero period leaves that axis alone.
+pub struct Repeat<S> {
+    sdf: S,
+    period: Vec3,
+}
+
+impl<S: Sdf> Repeat<S> {
+    pub fn new(sdf: S, period: Vec3) -> Self {
+        Repeat { sdf, period }
+    }
+}
+
+impl<S: Sdf> Sdf for Repeat<S> {
+    fn distance(&self, p: &Vec3) -> f32 {
+        let mut q = *p;
+        for i in 0..3 {
+            if self.period[i] != 0.0 {
+                q[i] -= self.period[i] * (p[i] / self.period[i]).round();
+            }
+        }
+        self.sdf.distance(&q)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        if self.period == Vec3::zero() {
+            self.sdf.bounding_box()
+        } else {
+            None
+        }
+    }
+}
+
+// Finds the surface of a field by sphere tracing: stepping along the ray by
+// the distance to the nearest surface until it is within `epsilon`. Normals
+// are the gradient of the field.
+pub struct SdfHitable<S> {
+    sdf: S,
+    material: Box<dyn Material>,
+    epsilon: f32,
+    max_steps: usize,
+    max_distance: f32,
+}
+
+impl<S: Sdf> SdfHitable<S> {
+    pub fn new(sdf: S, material: Box<dyn Material>) -> Self {
+        SdfHitable {
+            sdf,
+            material,
+            epsilon: 1e-4,
+            max_steps: 256,
+            max_distance: 1e3,
+        }
+    }
+
+    pub fn with_epsilon(mut self, epsilon: f32) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    // How far to march through unbounded fields before giving up.
+    pub fn with_max_distance(mut self, max_distance: f32) -> Self {
+        self.max_distance = max_distance;
+        self
+    }
+
+    pub fn sdf(&self) -> &S {
+        &self.sdf
+    }
+
+    pub fn normal(&self, p: &Vec3) -> Vec3 {
+        let h = 0.5 * self.epsilon;
+        let mut gradient = Vec3::zero();
+        for i in 0..3 {
+            let mut offset = Vec3::zero();
+            offset[i] = h;
+            gradient[i] = self.sdf.distance(&(*p + offset)) - self.sdf.distance(&(*p - offset));
+        }
+        gradient.unit_vector()
+    }
+}
+
+impl<S: Sdf> Hitable for SdfHitable<S> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        let len = r.direction().length();
+        let (mut t, t_end) = match self.sdf.bounding_box() {
+            Some(bbox) => bbox.padded(self.epsilon).range(r, t_min, t_max)?,
+            None => (t_min, t_max.min(t_min + self.max_distance / len)),
+        };
+
+        // Rays leaving a surface start on it, so work out which side they
+        // are heading into and only accept a hit once they have got clear.
+        let origin = r.point_at_parameter(t_min);
+        let start = self.sdf.distance(&origin);
+        let side = if start.abs() >= self.epsilon {
+            start.signum()
+        } else if Vec3::dot(&self.normal(&origin), r.direction()) > 0.0 {
+            1.0
+        } else {
+            -1.0
+        };
+        let mut clear = start.abs() >= self.epsilon;
+        for _ in 0..self.max_steps {
+            let p = r.point_at_parameter(t);
+            let d = side * self.sdf.distance(&p);
+            if d < self.epsilon && clear {
+                return Some(HitRecord::new(
+                    t,
+                    p,
+                    self.normal(&p),
+                    self.material.as_ref(),
+                ));
+            }
+            clear |= d >= self.epsilon;
+            t += d.max(self.epsilon) / len;
+            if t >= t_end {
+                break;
+            }
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.sdf
+            .bounding_box()
+            .map(|bbox| bbox.padded(self.epsilon))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BoxSdf, CapsuleSdf, Repeat, RoundedBoxSdf, Sdf, SdfHitable, SmoothSubtraction, SmoothUnion,
+        SphereSdf, TorusSdf,
+    };
+    use crate::{
+        hitable::Hitable,
+        ray::Ray,
+        test_support::{assert_near, grey},
+        vec3::Vec3,
+    };
+
+    #[test]
+    fn test_distances() {
+        let unit = Vec3::new(1.0, 1.0, 1.0);
+        let cube = BoxSdf::new(Vec3::zero(), unit);
+        assert_eq!(1.0, cube.distance(&Vec3::new(2.0, 0.0, 0.0)));
+        assert_eq!(-0.5, cube.distance(&Vec3::new(0.5, 0.0, 0.0)));
+        assert!((cube.distance(&Vec3::new(2.0, 2.0, 0.0)) - 2f32.sqrt()).abs() < 1e-3);
+        let rounded = RoundedBoxSdf::new(Vec3::zero(), unit, 0.5);
+        assert_eq!(1.0, rounded.distance(&Vec3::new(2.0, 0.0, 0.0)));
+        let distance = rounded.distance(&Vec3::new(1.5, 1.5, 0.0));
+        assert!((distance - (2f32.sqrt() - 0.5)).abs() < 1e-3);
+        let capsule = CapsuleSdf::new(Vec3::zero(), Vec3::new(0.0, 2.0, 0.0), 0.5);
+        assert_eq!(0.5, capsule.distance(&Vec3::new(1.0, 1.0, 0.0)));
+        assert_eq!(0.5, capsule.distance(&Vec3::new(0.0, 3.0, 0.0)));
+        let torus = TorusSdf::new(Vec3::zero(), 1.0, 0.25);
+        assert_eq!(-0.25, torus.distance(&Vec3::new(0.0, 0.0, 1.0)));
+        assert_eq!(0.75, torus.distance(&Vec3::zero()));
+        assert_eq!(
+            &Vec3::new(1.25, 0.25, 1.25),
+            torus.bounding_box().unwrap().max()
+        );
+    }
+
+    #[test]
+    fn test_combinators() {
+        let a = SphereSdf::new(Vec3::new(-1.0, 0.0, 0.0), 1.0);
+        let b = SphereSdf::new(Vec3::new(1.0, 0.0, 0.0), 1.0);
+        // Far from the seam the union is exact, and at it filled in.
+        let blend = SmoothUnion::new(a, b, 0.5);
+        assert_eq!(-1.0, blend.distance(&Vec3::new(-1.0, 0.0, 0.0)));
+        assert!(blend.distance(&Vec3::new(0.0, 0.1, 0.0)) < -0.1);
+        let carved = SmoothSubtraction::new(
+            SphereSdf::new(Vec3::zero(), 1.0),
+            SphereSdf::new(Vec3::new(1.0, 0.0, 0.0), 0.5),
+            0.1,
+        );
+        assert!(carved.distance(&Vec3::new(0.9, 0.0, 0.0)) > 0.0);
+        assert_eq!(-0.5, carved.distance(&Vec3::zero()));
+
+        let row = Repeat::new(SphereSdf::new(Vec3::zero(), 0.5), Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(-0.5, row.distance(&Vec3::new(6.0, 0.0, 0.0)));
+        assert_eq!(0.5, row.distance(&Vec3::new(7.0, 0.0, 0.0)));
+        assert_eq!(2.5, row.distance(&Vec3::new(6.0, 3.0, 0.0)));
+        assert!(row.bounding_box().is_none());
+    }
+
+    #[test]
+    fn test_sphere_tracing() {
+        let sphere = SdfHitable::new(SphereSdf::new(Vec3::new(0.0, 0.0, -5.0), 1.0), grey());
+        let r = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -2.0));
+        let hit = sphere.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((hit.t() - 2.0).abs() < 1e-3);
+        assert_near(&Vec3::new(0.0, 0.0, 1.0), hit.normal(), 1e-3);
+        // Leaving the surface inwards finds the far side.
+        let inward = Ray::new(*hit.p(), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere.hit(&inward, 0.0, f32::MAX).unwrap();
+        assert!((hit.t() - 2.0).abs() < 1e-3);
+        assert_near(&Vec3::new(0.0, 0.0, -1.0), hit.normal(), 1e-3);
+        // And outwards nothing.
+        let outward = Ray::new(*hit.p(), Vec3::new(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&outward, 0.0, f32::MAX).is_none());
+        let miss = Ray::new(Vec3::zero(), Vec3::new(0.0, 1.0, -2.0));
+        assert!(sphere.hit(&miss, 0.001, f32::MAX).is_none());
+
+        // An endless row of boxes, the third of them in the way.
+        let row = SdfHitable::new(
+            Repeat::new(
+                BoxSdf::new(Vec3::zero(), Vec3::new(0.5, 0.5, 0.5)),
+                Vec3::new(4.0, 0.0, 0.0),
+            ),
+            grey(),
+        );
+        let r = Ray::new(Vec3::new(8.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = row.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((hit.t() - 4.5).abs() < 1e-3);
+        assert_near(&Vec3::new(0.0, 1.0, 0.0), hit.normal(), 1e-3);
+        assert!(row.hit(&r, 0.001, 4.0).is_none());
+    }
+}
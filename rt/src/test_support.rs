@@ -0,0 +1,10 @@
+// Helpers shared by the unit tests.
+use crate::{material::Lambertian, vec3::Vec3};
+
+pub fn grey() -> Box<Lambertian> {
+    Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))
+}
+
+pub fn assert_near(a: &Vec3, b: &Vec3, tolerance: f32) {
+    assert!((*a - *b).length() < tolerance, "{:?} {:?}", a, b);
+}
@@ -0,0 +1,432 @@
+use crate::{image::Image, vec3::Vec3};
+use std::io;
+use std::path::Path;
+
+// A color that varies over a surface, looked up by its surface coordinates
+// and the point hit.
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3;
+
+    // The average over the area a pixel covers, where that is known. Only
+    // image textures filter, and patterns of other textures pass it on to
+    // them; the rest are looked up at the center.
+    fn filtered(&self, u: f32, v: f32, p: &Vec3, _footprint: &Footprint) -> Vec3 {
+        self.value(u, v, p)
+    }
+}
+
+// How far the surface coordinates move from one pixel to the next across
+// and up the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Footprint {
+    pub dudx: f32,
+    pub dvdx: f32,
+    pub dudy: f32,
+    pub dvdy: f32,
+}
+
+pub struct SolidColor {
+    color: Vec3,
+}
+
+impl SolidColor {
+    pub fn new(color: Vec3) -> Self {
+        SolidColor { color }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _: f32, _: f32, _: &Vec3) -> Vec3 {
+        self.color
+    }
+}
+
+// Alternates between two textures in cubes of `size` filling space, so that
+// it needs no surface coordinates.
+pub struct Checker {
+    size: f32,
+    even: Box<dyn Texture>,
+    odd: Box<dyn Texture>,
+}
+
+impl Checker {
+    pub fn new(size: f32, even: Box<dyn Texture>, odd: Box<dyn Texture>) -> Self {
+        Checker { size, even, odd }
+    }
+
+    fn is_even(&self, p: &Vec3) -> bool {
+        let cell =
+            (p.x() / self.size).floor() + (p.y() / self.size).floor() + (p.z() / self.size).floor();
+        cell.rem_euclid(2.0) == 0.0
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f32, v: f32, p: &Vec3) -> Vec3 {
+        if self.is_even(p) {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+
+    fn filtered(&self, u: f32, v: f32, p: &Vec3, footprint: &Footprint) -> Vec3 {
+        if self.is_even(p) {
+            self.even.filtered(u, v, p, footprint)
+        } else {
+            self.odd.filtered(u, v, p, footprint)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Filter {
+    // The full size image only, whatever the footprint.
+    Bilinear,
+    // Between the two mip levels whose texels are nearest the footprint in
+    // size.
+    Trilinear,
+    // A Gaussian over the elliptical footprint, for surfaces seen at a
+    // grazing angle.
+    Ewa,
+}
+
+// Elliptical footprints are made no longer than this many times their
+// width, which bounds the number of texels read.
+const MAX_ANISOTROPY: f32 = 8.0;
+
+// An image stretched over the unit square of surface coordinates, with v
+// going up the image, and repeated beyond it. Unfiltered lookups are
+// bilinear.
+pub struct ImageTexture {
+    // The image followed by copies of half the size, down to a single texel.
+    levels: Vec<Image>,
+    filter: Filter,
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        let mut levels = vec![image];
+        loop {
+            let last = &levels[levels.len() - 1];
+            if last.width() * last.height() <= 1 {
+                break;
+            }
+            let next = downsample(last);
+            levels.push(next);
+        }
+        ImageTexture {
+            levels,
+            filter: Filter::Trilinear,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(ImageTexture::new(Image::load(path)?))
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.levels[0]
+    }
+
+    pub fn levels(&self) -> &[Image] {
+        &self.levels
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    // The fractional mip level whose texels are `width` texels of the full
+    // size image across.
+    fn level(&self, width: f32) -> f32 {
+        width.max(1.0).log2().min((self.levels.len() - 1) as f32)
+    }
+
+    // Blends two adjacent levels looked up by `lookup`.
+    fn between_levels(&self, level: f32, lookup: impl Fn(&Image) -> Vec3) -> Vec3 {
+        let lower = level.floor() as usize;
+        let upper = (lower + 1).min(self.levels.len() - 1);
+        let f = level - lower as f32;
+        (1.0 - f) * lookup(&self.levels[lower]) + f * lookup(&self.levels[upper])
+    }
+
+    fn trilinear(&self, u: f32, v: f32, footprint: &Footprint) -> Vec3 {
+        let image = self.image();
+        let (w, h) = (image.width() as f32, image.height() as f32);
+        let width = (footprint.dudx * w)
+            .hypot(footprint.dvdx * h)
+            .max((footprint.dudy * w).hypot(footprint.dvdy * h));
+        self.between_levels(self.level(width), |image| bilinear(image, u, v))
+    }
+
+    fn ewa(&self, u: f32, v: f32, footprint: &Footprint) -> Vec3 {
+        let image = self.image();
+        let (w, h) = (image.width() as f32, image.height() as f32);
+        // The axes in texels of the full size image.
+        let mut major = (footprint.dudx * w, footprint.dvdx * h);
+        let mut minor = (footprint.dudy * w, footprint.dvdy * h);
+        let length = |a: (f32, f32)| a.0.hypot(a.1);
+        if length(minor) > length(major) {
+            std::mem::swap(&mut major, &mut minor);
+        }
+        let (major_length, minor_length) = (length(major), length(minor));
+        if minor_length * MAX_ANISOTROPY < major_length {
+            let scale = major_length / (minor_length * MAX_ANISOTROPY);
+            if !scale.is_finite() {
+                // A footprint with no width; give it some across the major
+                // axis.
+                let width = major_length / MAX_ANISOTROPY;
+                minor = (
+                    -major.1 / major_length * width,
+                    major.0 / major_length * width,
+                );
+            } else {
+                minor = (minor.0 * scale, minor.1 * scale);
+            }
+        }
+        if major_length == 0.0 {
+            return bilinear(image, u, v);
+        }
+        let level = self.level(length(minor));
+        let last = self.levels.len() - 1;
+        if level >= last as f32 {
+            // The whole image, already averaged.
+            return bilinear(&self.levels[last], u, v);
+        }
+        self.between_levels(level, |level| {
+            let scale = (level.width() as f32 / w, level.height() as f32 / h);
+            // An axis longer than the level wraps around it, and would only
+            // read the same texels again.
+            let limit = level.width().max(level.height()) as f32;
+            let axis = |a: (f32, f32)| {
+                let a = (a.0 * scale.0, a.1 * scale.1);
+                let l = length(a);
+                if l > limit {
+                    (a.0 * limit / l, a.1 * limit / l)
+                } else {
+                    a
+                }
+            };
+            ewa(level, u, v, axis(major), axis(minor))
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f32, v: f32, _: &Vec3) -> Vec3 {
+        bilinear(self.image(), u, v)
+    }
+
+    fn filtered(&self, u: f32, v: f32, _: &Vec3, footprint: &Footprint) -> Vec3 {
+        if self.image().width() == 0 || self.image().height() == 0 {
+            return Vec3::zero();
+        }
+        match self.filter {
+            Filter::Bilinear => bilinear(self.image(), u, v),
+            Filter::Trilinear => self.trilinear(u, v, footprint),
+            Filter::Ewa => self.ewa(u, v, footprint),
+        }
+    }
+}
+
+// Averages blocks of two by two texels, wrapping around the far edges of
+// images with an odd size.
+fn downsample(image: &Image) -> Image {
+    let (width, height) = (image.width(), image.height());
+    let (w, h) = (width.div_ceil(2).max(1), height.div_ceil(2).max(1));
+    let mut pixels = Vec::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            let mut sum = Vec3::zero();
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                sum += image.pixel((2 * x + dx) % width, (2 * y + dy) % height);
+            }
+            pixels.push(sum / 4.0);
+        }
+    }
+    Image::from_pixels(w, h, pixels)
+}
+
+fn texel(image: &Image, x: isize, y: isize) -> Vec3 {
+    image.pixel(
+        x.rem_euclid(image.width() as isize) as usize,
+        y.rem_euclid(image.height() as isize) as usize,
+    )
+}
+
+fn bilinear(image: &Image, u: f32, v: f32) -> Vec3 {
+    let (width, height) = (image.width(), image.height());
+    if width == 0 || height == 0 {
+        return Vec3::zero();
+    }
+    let x = u * width as f32 - 0.5;
+    let y = (1.0 - v) * height as f32 - 0.5;
+    let (fx, fy) = (x - x.floor(), y - y.floor());
+    let (x0, y0) = (x.floor() as isize, y.floor() as isize);
+    let top = (1.0 - fx) * texel(image, x0, y0) + fx * texel(image, x0 + 1, y0);
+    let bottom = (1.0 - fx) * texel(image, x0, y0 + 1) + fx * texel(image, x0 + 1, y0 + 1);
+    (1.0 - fy) * top + fy * bottom
+}
+
+// A Gaussian weighted average of the texels of `image` inside the ellipse
+// around (u, v) with axes `a` and `b`, given in its texels along u and v.
+fn ewa(image: &Image, u: f32, v: f32, a: (f32, f32), b: (f32, f32)) -> Vec3 {
+    const ALPHA: f32 = 2.0;
+    let s = u * image.width() as f32 - 0.5;
+    let t = (1.0 - v) * image.height() as f32 - 0.5;
+    // Image rows go down while v goes up.
+    let (a, b) = ((a.0, -a.1), (b.0, -b.1));
+    // The implicit ellipse A s^2 + B s t + C t^2 = 1, widened by a texel so
+    // that it always covers some.
+    let mut ea = a.1 * a.1 + b.1 * b.1 + 1.0;
+    let mut eb = -2.0 * (a.0 * a.1 + b.0 * b.1);
+    let mut ec = a.0 * a.0 + b.0 * b.0 + 1.0;
+    let f = ea * ec - eb * eb / 4.0;
+    ea /= f;
+    eb /= f;
+    ec /= f;
+    let det = 4.0 * ea * ec - eb * eb;
+    let (half_s, half_t) = (2.0 * (ec / det).sqrt(), 2.0 * (ea / det).sqrt());
+    let (s0, s1) = ((s - half_s).ceil() as isize, (s + half_s).floor() as isize);
+    let (t0, t1) = ((t - half_t).ceil() as isize, (t + half_t).floor() as isize);
+    let mut sum = Vec3::zero();
+    let mut total = 0.0;
+    for y in t0..=t1 {
+        let dt = y as f32 - t;
+        for x in s0..=s1 {
+            let ds = x as f32 - s;
+            let r2 = ea * ds * ds + eb * ds * dt + ec * dt * dt;
+            if r2 < 1.0 {
+                let weight = (-ALPHA * r2).exp() - (-ALPHA).exp();
+                sum += weight * texel(image, x, y);
+                total += weight;
+            }
+        }
+    }
+    if total > 0.0 {
+        sum / total
+    } else {
+        bilinear(image, u, v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checker, Filter, Footprint, ImageTexture, SolidColor, Texture};
+    use crate::{image::Image, vec3::Vec3};
+
+    #[test]
+    fn test_checker() {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let checker = Checker::new(
+            0.5,
+            Box::new(SolidColor::new(white)),
+            Box::new(SolidColor::new(Vec3::zero())),
+        );
+        assert_eq!(white, checker.value(0.0, 0.0, &Vec3::new(0.1, 0.1, 0.1)));
+        assert_eq!(
+            Vec3::zero(),
+            checker.value(0.0, 0.0, &Vec3::new(0.6, 0.1, 0.1))
+        );
+        assert_eq!(
+            Vec3::zero(),
+            checker.value(0.0, 0.0, &Vec3::new(-0.1, 0.1, 0.1))
+        );
+        assert_eq!(white, checker.value(0.0, 0.0, &Vec3::new(-0.1, -0.1, 0.1)));
+    }
+
+    #[test]
+    fn test_image_texture() {
+        // Red on top of blue.
+        let red = Vec3::new(1.0, 0.0, 0.0);
+        let blue = Vec3::new(0.0, 0.0, 1.0);
+        let texture = ImageTexture::new(Image::from_pixels(1, 2, vec![red, blue]));
+        let p = Vec3::zero();
+        assert_eq!(red, texture.value(0.5, 0.75, &p));
+        assert_eq!(blue, texture.value(0.5, 0.25, &p));
+        assert_eq!(0.5 * (red + blue), texture.value(0.0, 0.5, &p));
+        // Repeated past the edges.
+        assert_eq!(blue, texture.value(3.5, 1.25, &p));
+    }
+
+    // Columns alternately white and black.
+    fn stripes(width: usize, height: usize) -> Image {
+        let pixels = (0..width * height)
+            .map(|i| Vec3::new(1.0, 1.0, 1.0) * ((i % width) % 2) as f32)
+            .collect();
+        Image::from_pixels(width, height, pixels)
+    }
+
+    fn footprint(dudx: f32, dvdy: f32) -> Footprint {
+        Footprint {
+            dudx,
+            dvdx: 0.0,
+            dudy: 0.0,
+            dvdy,
+        }
+    }
+
+    #[test]
+    fn test_mip_levels() {
+        let texture = ImageTexture::new(stripes(4, 2));
+        let sizes: Vec<(usize, usize)> = texture
+            .levels()
+            .iter()
+            .map(|level| (level.width(), level.height()))
+            .collect();
+        assert_eq!(vec![(4, 2), (2, 1), (1, 1)], sizes);
+        let grey = Vec3::new(0.5, 0.5, 0.5);
+        assert!(texture.levels()[1].pixels().iter().all(|p| *p == grey));
+        assert_eq!(grey, texture.levels()[2].pixel(0, 0));
+        // Odd sizes wrap around.
+        assert_eq!(4, ImageTexture::new(stripes(5, 5)).levels().len());
+    }
+
+    #[test]
+    fn test_filters() {
+        let p = Vec3::zero();
+        let grey = Vec3::new(0.5, 0.5, 0.5);
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        // The center of a white column.
+        let (u, v) = (1.5 / 16.0, 0.5);
+        for filter in [Filter::Bilinear, Filter::Trilinear, Filter::Ewa] {
+            let texture = ImageTexture::new(stripes(16, 16)).with_filter(filter);
+            // Less than a texel gives the unfiltered value.
+            let small = footprint(1e-3, 1e-3);
+            assert!((texture.filtered(u, v, &p, &small) - white).length() < 1e-5);
+            // Many texels in both directions average out.
+            let large = texture.filtered(u, v, &p, &footprint(0.5, 0.5));
+            if filter == Filter::Bilinear {
+                assert_eq!(white, large);
+            } else {
+                assert!((large - grey).length() < 1e-3, "{:?}", large);
+            }
+        }
+
+        // Stretched along the columns, only EWA keeps them apart, blurred by
+        // the clamp on its eccentricity.
+        let along = footprint(1e-3, 0.5);
+        let trilinear = ImageTexture::new(stripes(16, 16));
+        assert!((trilinear.filtered(u, v, &p, &along) - grey).length() < 1e-3);
+        let ewa = ImageTexture::new(stripes(16, 16)).with_filter(Filter::Ewa);
+        assert!(ewa.filtered(u, v, &p, &along).x() > 0.6);
+        let across = footprint(0.5, 1e-3);
+        assert!((ewa.filtered(u, v, &p, &across) - grey).length() < 0.1);
+
+        // Footprints far larger than the image, as near the horizon of a
+        // plane, cost no more than small ones.
+        for huge in [
+            footprint(1e5, 1e5),
+            footprint(1e5, 1e-3),
+            footprint(4.0, 0.5),
+        ] {
+            assert!((ewa.filtered(u, v, &p, &huge) - grey).length() < 1e-3);
+        }
+    }
+}
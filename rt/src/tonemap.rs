@@ -0,0 +1,116 @@
+use crate::vec3::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Clamp,
+    Reinhard,
+    // Narkowicz's curve fit of the ACES reference rendering transform.
+    Aces,
+}
+
+// Turns linear scene radiance into display values: scales by the exposure
+// (in stops), compresses with the operator and encodes with the sRGB curve.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneMap {
+    operator: Operator,
+    exposure: f32,
+}
+
+impl ToneMap {
+    pub fn new(operator: Operator, exposure: f32) -> Self {
+        ToneMap { operator, exposure }
+    }
+
+    pub fn operator(&self) -> Operator {
+        self.operator
+    }
+
+    pub fn exposure(&self) -> f32 {
+        self.exposure
+    }
+
+    // Linear values in [0, 1], before the sRGB encoding.
+    pub fn map(&self, col: &Vec3) -> Vec3 {
+        let col = *col * 2f32.powf(self.exposure);
+        let mut out = Vec3::zero();
+        for i in 0..3 {
+            let x = col[i].max(0.0);
+            out[i] = match self.operator {
+                Operator::Clamp => x,
+                Operator::Reinhard => x / (1.0 + x),
+                Operator::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14),
+            }
+            .min(1.0);
+        }
+        out
+    }
+
+    pub fn to_srgb8(&self, col: &Vec3) -> [u8; 3] {
+        let col = self.map(col);
+        let mut out = [0; 3];
+        for (i, v) in out.iter_mut().enumerate() {
+            *v = (255.0 * srgb_oetf(col[i]) + 0.5) as u8;
+        }
+        out
+    }
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        ToneMap::new(Operator::Clamp, 0.0)
+    }
+}
+
+pub fn srgb_oetf(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+pub fn srgb_eotf(x: f32) -> f32 {
+    if x <= 0.040_45 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{srgb_eotf, srgb_oetf, Operator, ToneMap};
+    use crate::vec3::Vec3;
+
+    #[test]
+    fn test_srgb() {
+        assert_eq!(0.0, srgb_oetf(0.0));
+        assert!((srgb_oetf(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_oetf(0.18) - 0.4614).abs() < 1e-3);
+        for &x in &[0.001, 0.0031308, 0.2, 0.5, 0.9] {
+            assert!((srgb_eotf(srgb_oetf(x)) - x).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_operators() {
+        let bright = Vec3::new(0.5, 4.0, 100.0);
+        let clamp = ToneMap::default();
+        assert_eq!(Vec3::new(0.5, 1.0, 1.0), clamp.map(&bright));
+        assert_eq!([188, 255, 255], clamp.to_srgb8(&bright));
+
+        let reinhard = ToneMap::new(Operator::Reinhard, 0.0);
+        let aces = ToneMap::new(Operator::Aces, 0.0);
+        for tone_map in [reinhard, aces].iter() {
+            let mapped = tone_map.map(&bright);
+            assert!(mapped.r() < mapped.g() && mapped.g() < mapped.b() && mapped.b() <= 1.0);
+        }
+
+        let brighter = ToneMap::new(Operator::Reinhard, 1.0);
+        assert_eq!(
+            reinhard.map(&Vec3::new(1.0, 1.0, 1.0)),
+            brighter.map(&Vec3::new(0.5, 0.5, 0.5))
+        );
+        assert_eq!([0, 0, 0], aces.to_srgb8(&Vec3::new(-1.0, 0.0, 0.0)));
+    }
+}
@@ -0,0 +1,806 @@
+use crate::{
+    aabb::Aabb,
+    hitable::{HitRecord, Hitable},
+    ray::{Differentials, Ray},
+    vec3::Vec3,
+};
+use std::ops::Mul;
+
+// Row major, acting on column vectors.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4([[f32; 4]; 4]);
+
+impl Mat4 {
+    pub fn new(rows: [[f32; 4]; 4]) -> Self {
+        Mat4(rows)
+    }
+
+    // For column major data such as glTF's.
+    pub fn from_columns(columns: [[f32; 4]; 4]) -> Self {
+        Mat4(columns).transpose()
+    }
+
+    pub fn identity() -> Self {
+        Mat4([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn rows(&self) -> &[[f32; 4]; 4] {
+        &self.0
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.0[j][i];
+            }
+        }
+        Mat4(m)
+    }
+
+    // None if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        // Gauss-Jordan elimination with partial pivoting.
+        let mut a = self.0;
+        let mut inv = Mat4::identity().0;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())
+                .unwrap();
+            if a[pivot][col] == 0.0 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Mat4(inv))
+    }
+
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.0;
+        let mut out = Vec3::zero();
+        for (i, row) in m.iter().take(3).enumerate() {
+            out[i] = row[0] * p.x() + row[1] * p.y() + row[2] * p.z() + row[3];
+        }
+        let w = m[3][0] * p.x() + m[3][1] * p.y() + m[3][2] * p.z() + m[3][3];
+        if w == 1.0 {
+            out
+        } else {
+            out / w
+        }
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let mut out = Vec3::zero();
+        for (i, row) in self.0.iter().take(3).enumerate() {
+            out[i] = row[0] * v.x() + row[1] * v.y() + row[2] * v.z();
+        }
+        out
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[i][k] * rhs.0[k][j]).sum();
+            }
+        }
+        Mat4(m)
+    }
+}
+
+// An invertible affine transform with its inverse kept alongside. `a * b`
+// applies `b` first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    m: Mat4,
+    inv: Mat4,
+}
+
+impl Transform {
+    // None if the matrix cannot be inverted.
+    pub fn new(m: Mat4) -> Option<Self> {
+        Some(Transform {
+            inv: m.inverse()?,
+            m,
+        })
+    }
+
+    pub fn identity() -> Self {
+        Transform {
+            m: Mat4::identity(),
+            inv: Mat4::identity(),
+        }
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        let m = |d: Vec3| {
+            Mat4([
+                [1.0, 0.0, 0.0, d.x()],
+                [0.0, 1.0, 0.0, d.y()],
+                [0.0, 0.0, 1.0, d.z()],
+                [0.0, 0.0, 0.0, 1.0],
+            ])
+        };
+        Transform {
+            m: m(offset),
+            inv: m(-offset),
+        }
+    }
+
+    // Panics on a zero factor.
+    pub fn scale(factors: Vec3) -> Self {
+        assert!(factors.x() != 0.0 && factors.y() != 0.0 && factors.z() != 0.0);
+        let m = |s: Vec3| {
+            Mat4([
+                [s.x(), 0.0, 0.0, 0.0],
+                [0.0, s.y(), 0.0, 0.0],
+                [0.0, 0.0, s.z(), 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ])
+        };
+        Transform {
+            m: m(factors),
+            inv: m(Vec3::new(
+                1.0 / factors.x(),
+                1.0 / factors.y(),
+                1.0 / factors.z(),
+            )),
+        }
+    }
+
+    // Counter-clockwise in degrees when looking down `axis` towards the origin.
+    pub fn rotate(axis: &Vec3, degrees: f32) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+        let m = Mat4([
+            [
+                t * a.x() * a.x() + cos,
+                t * a.x() * a.y() - sin * a.z(),
+                t * a.x() * a.z() + sin * a.y(),
+                0.0,
+            ],
+            [
+                t * a.x() * a.y() + sin * a.z(),
+                t * a.y() * a.y() + cos,
+                t * a.y() * a.z() - sin * a.x(),
+                0.0,
+            ],
+            [
+                t * a.x() * a.z() - sin * a.y(),
+                t * a.y() * a.z() + sin * a.x(),
+                t * a.z() * a.z() + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        // Rotations are orthogonal.
+        Transform {
+            m,
+            inv: m.transpose(),
+        }
+    }
+
+    pub fn rotate_x(degrees: f32) -> Self {
+        Transform::rotate(&Vec3::new(1.0, 0.0, 0.0), degrees)
+    }
+
+    pub fn rotate_y(degrees: f32) -> Self {
+        Transform::rotate(&Vec3::new(0.0, 1.0, 0.0), degrees)
+    }
+
+    pub fn rotate_z(degrees: f32) -> Self {
+        Transform::rotate(&Vec3::new(0.0, 0.0, 1.0), degrees)
+    }
+
+    pub fn matrix(&self) -> &Mat4 {
+        &self.m
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        self.m.transform_point(p)
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        self.m.transform_vector(v)
+    }
+
+    // By the inverse transpose, which keeps normals perpendicular to the
+    // surface under non-uniform scaling. The result is not normalized.
+    pub fn transform_normal(&self, n: &Vec3) -> Vec3 {
+        self.inv.transpose().transform_vector(n)
+    }
+
+    // The direction is not normalized, so a hit keeps its t in both spaces.
+    pub fn transform_ray(&self, r: &Ray) -> Ray {
+        let ray = Ray::new(
+            self.transform_point(r.origin()),
+            self.transform_vector(r.direction()),
+        )
+        .with_time(r.time());
+        match r.differentials() {
+            Some(d) => ray.with_differentials(Differentials {
+                rx_origin: self.transform_point(&d.rx_origin),
+                rx_direction: self.transform_vector(&d.rx_direction),
+                ry_origin: self.transform_point(&d.ry_origin),
+                ry_direction: self.transform_vector(&d.ry_direction),
+            }),
+            None => ray,
+        }
+    }
+
+    pub fn transform_aabb(&self, bbox: &Aabb) -> Aabb {
+        let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = -min;
+        for corner in 0..8 {
+            let p = Vec3::new(
+                if corner & 1 == 0 {
+                    bbox.min().x()
+                } else {
+                    bbox.max().x()
+                },
+                if corner & 2 == 0 {
+                    bbox.min().y()
+                } else {
+                    bbox.max().y()
+                },
+                if corner & 4 == 0 {
+                    bbox.min().z()
+                } else {
+                    bbox.max().z()
+                },
+            );
+            let p = self.transform_point(&p);
+            for i in 0..3 {
+                min[i] = min[i].min(p[i]);
+                max[i] = max[i].max(p[i]);
+            }
+        }
+        Aabb::new(min, max)
+    }
+}
+
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul(self, rhs: Transform) -> Transform {
+        Transform {
+            m: self.m * rhs.m,
+            inv: rhs.inv * self.inv,
+        }
+    }
+}
+
+// A transform that changes over time, interpolated between `start` at `time0`
+// and `end` at `time1` and held before and after. Both ends are split into a
+// translation, a rotation and a stretch that are interpolated separately, so
+// that objects turn without shrinking on the way.
+#[derive(Clone, Copy, Debug)]
+pub struct AnimatedTransform {
+    start: Transform,
+    end: Transform,
+    time0: f32,
+    time1: f32,
+    parts: [Decomposed; 2],
+}
+
+impl AnimatedTransform {
+    pub fn new(start: Transform, end: Transform, time0: f32, time1: f32) -> Self {
+        AnimatedTransform {
+            parts: [decompose(&start.m), decompose(&end.m)],
+            start,
+            end,
+            time0,
+            time1,
+        }
+    }
+
+    pub fn at(&self, time: f32) -> Transform {
+        if time <= self.time0 || self.time1 <= self.time0 {
+            return self.start;
+        }
+        if time >= self.time1 {
+            return self.end;
+        }
+        let s = (time - self.time0) / (self.time1 - self.time0);
+        let [a, b] = &self.parts;
+        let translation = a.translation + s * (b.translation - a.translation);
+        let rotation = slerp(&a.rotation, &b.rotation, s);
+        let mut m = rotation_matrix(&rotation) * lerp(&a.stretch, &b.stretch, s);
+        for i in 0..3 {
+            m.0[i][3] = translation[i];
+        }
+        Transform::new(m).unwrap_or(self.start)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Decomposed {
+    translation: Vec3,
+    // A unit quaternion, (x, y, z, w).
+    rotation: [f32; 4],
+    stretch: Mat4,
+}
+
+// Polar decomposition of the upper 3x3 by averaging with the inverse
+// transpose until only the rotation is left (Shoemake and Duff, 1992).
+fn decompose(m: &Mat4) -> Decomposed {
+    let translation = Vec3::new(m.0[0][3], m.0[1][3], m.0[2][3]);
+    let mut upper = *m;
+    for i in 0..3 {
+        upper.0[i][3] = 0.0;
+        upper.0[3][i] = 0.0;
+    }
+    upper.0[3][3] = 1.0;
+    let mut rotation = upper;
+    for _ in 0..100 {
+        let inverse_transpose = match rotation.inverse() {
+            Some(inverse) => inverse.transpose(),
+            None => break,
+        };
+        let next = lerp(&rotation, &inverse_transpose, 0.5);
+        let change: f32 = (0..3)
+            .map(|i| {
+                (0..3)
+                    .map(|j| (next.0[i][j] - rotation.0[i][j]).abs())
+                    .sum::<f32>()
+            })
+            .fold(0.0, f32::max);
+        rotation = next;
+        if change < 1e-6 {
+            break;
+        }
+    }
+    // A mirroring transform leaves a reflection, which no quaternion holds;
+    // its sign goes into the stretch instead.
+    let row = |i: usize| Vec3::new(rotation.0[i][0], rotation.0[i][1], rotation.0[i][2]);
+    if Vec3::dot(&row(0), &Vec3::cross(&row(1), &row(2))) < 0.0 {
+        for i in 0..3 {
+            for j in 0..3 {
+                rotation.0[i][j] = -rotation.0[i][j];
+            }
+        }
+    }
+    Decomposed {
+        translation,
+        rotation: quaternion(&rotation),
+        stretch: rotation.transpose() * upper,
+    }
+}
+
+fn lerp(a: &Mat4, b: &Mat4, s: f32) -> Mat4 {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (1.0 - s) * a.0[i][j] + s * b.0[i][j];
+        }
+    }
+    Mat4(m)
+}
+
+fn quaternion(m: &Mat4) -> [f32; 4] {
+    let m = &m.0;
+    let trace = m[0][0] + m[1][1] + m[2][2];
+    let q = if trace > 0.0 {
+        let s = 0.5 / (trace + 1.0).sqrt();
+        [
+            (m[2][1] - m[1][2]) * s,
+            (m[0][2] - m[2][0]) * s,
+            (m[1][0] - m[0][1]) * s,
+            0.25 / s,
+        ]
+    } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+        let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+        [
+            0.25 * s,
+            (m[0][1] + m[1][0]) / s,
+            (m[0][2] + m[2][0]) / s,
+            (m[2][1] - m[1][2]) / s,
+        ]
+    } else if m[1][1] > m[2][2] {
+        let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+        [
+            (m[0][1] + m[1][0]) / s,
+            0.25 * s,
+            (m[1][2] + m[2][1]) / s,
+            (m[0][2] - m[2][0]) / s,
+        ]
+    } else {
+        let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+        [
+            (m[0][2] + m[2][0]) / s,
+            (m[1][2] + m[2][1]) / s,
+            0.25 * s,
+            (m[1][0] - m[0][1]) / s,
+        ]
+    };
+    let len = q.iter().map(|x| x * x).sum::<f32>().sqrt();
+    q.map(|x| x / len)
+}
+
+fn slerp(a: &[f32; 4], b: &[f32; 4], s: f32) -> [f32; 4] {
+    let mut cos: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    // Go the short way round.
+    let b = if cos < 0.0 {
+        cos = -cos;
+        b.map(|x| -x)
+    } else {
+        *b
+    };
+    let (wa, wb) = if cos > 0.9995 {
+        (1.0 - s, s)
+    } else {
+        let theta = cos.acos();
+        (
+            ((1.0 - s) * theta).sin() / theta.sin(),
+            (s * theta).sin() / theta.sin(),
+        )
+    };
+    let mut q = [0.0; 4];
+    for i in 0..4 {
+        q[i] = wa * a[i] + wb * b[i];
+    }
+    let len = q.iter().map(|x| x * x).sum::<f32>().sqrt();
+    q.map(|x| x / len)
+}
+
+fn rotation_matrix(q: &[f32; 4]) -> Mat4 {
+    let [x, y, z, w] = *q;
+    Mat4([
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y - z * w),
+            2.0 * (x * z + y * w),
+            0.0,
+        ],
+        [
+            2.0 * (x * y + z * w),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z - x * w),
+            0.0,
+        ],
+        [
+            2.0 * (x * z - y * w),
+            2.0 * (y * z + x * w),
+            1.0 - 2.0 * (x * x + y * y),
+            0.0,
+        ],
+        [0.0, 0.0, 0.0, 1.0],
+    ])
+}
+
+// An object placed in the world by a transform. Wrap an `Arc` to place one
+// object, e.g. a large mesh, many times without copying it.
+pub struct Transformed<H> {
+    object: H,
+    transform: Transform,
+}
+
+impl<H: Hitable> Transformed<H> {
+    pub fn new(object: H, transform: Transform) -> Self {
+        Transformed { object, transform }
+    }
+
+    pub fn object(&self) -> &H {
+        &self.object
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+impl<H: Hitable> Hitable for Transformed<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_transformed(&self.object, &self.transform, r, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.object
+            .bounding_box()
+            .map(|bbox| self.transform.transform_aabb(&bbox))
+    }
+}
+
+// An object moved by an animated transform, hit where it is at the time of
+// each ray.
+pub struct Animated<H> {
+    object: H,
+    motion: AnimatedTransform,
+}
+
+impl<H: Hitable> Animated<H> {
+    pub fn new(object: H, motion: AnimatedTransform) -> Self {
+        Animated { object, motion }
+    }
+}
+
+impl<H: Hitable> Hitable for Animated<H> {
+    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'_>> {
+        hit_transformed(&self.object, &self.motion.at(r.time()), r, t_min, t_max)
+    }
+
+    // The union of the boxes at closely spaced times, padded for the arcs
+    // that rotating corners sweep between them.
+    fn bounding_box(&self) -> Option<Aabb> {
+        const STEPS: usize = 64;
+        let bbox = self.object.bounding_box()?;
+        let (time0, time1) = (self.motion.time0, self.motion.time1);
+        let mut swept = self.motion.start.transform_aabb(&bbox);
+        for step in 1..=STEPS {
+            let time = time0 + (time1 - time0) * step as f32 / STEPS as f32;
+            let moved = self.motion.at(time).transform_aabb(&bbox);
+            swept = Aabb::surrounding(&swept, &moved);
+        }
+        Some(swept.padded(1e-3 * (*swept.max() - *swept.min()).length()))
+    }
+}
+
+fn hit_transformed<'a, H: Hitable>(
+    object: &'a H,
+    transform: &Transform,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord<'a>> {
+    let local = transform.inverse().transform_ray(r);
+    let hit = object.hit(&local, t_min, t_max)?;
+    let p = transform.transform_point(hit.p());
+    let normal = transform.transform_normal(hit.normal()).unit_vector();
+    let shading_normal = transform
+        .transform_normal(hit.shading_normal())
+        .unit_vector();
+    let dpdu = transform.transform_vector(hit.dpdu());
+    let dpdv = transform.transform_vector(hit.dpdv());
+    Some(
+        hit.with_p(p)
+            .with_normal(normal)
+            .with_shading_normal(shading_normal)
+            .with_derivatives(dpdu, dpdv),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Animated, AnimatedTransform, Mat4, Transform, Transformed};
+    use crate::{
+        bvh::Bvh,
+        hitable::{Hitable, HitableList, Sphere},
+        mesh::TriangleMesh,
+        ray::Ray,
+        test_support::{assert_near, grey},
+        vec3::Vec3,
+    };
+    use std::sync::Arc;
+
+    #[test]
+    fn test_inverse() {
+        let m = Mat4::new([
+            [2.0, 0.0, 1.0, 3.0],
+            [0.0, 0.0, 4.0, -1.0],
+            [1.0, 3.0, 0.0, 2.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let product = m * m.inverse().unwrap();
+        for (i, row) in product.rows().iter().enumerate() {
+            for (j, value) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((value - expected).abs() < 1e-6, "{:?}", product);
+            }
+        }
+        let singular = Mat4::new([
+            [1.0, 2.0, 0.0, 0.0],
+            [2.0, 4.0, 0.0, 0.0],
+            [0.0; 4],
+            [0.0; 4],
+        ]);
+        assert!(singular.inverse().is_none());
+        assert!(Transform::new(singular).is_none());
+    }
+
+    #[test]
+    fn test_compose() {
+        let t = Transform::translate(Vec3::new(1.0, 0.0, 0.0)) * Transform::rotate_y(90.0);
+        assert_near(
+            &Vec3::new(1.0, 0.0, -1.0),
+            &t.transform_point(&Vec3::new(1.0, 0.0, 0.0)),
+            1e-5,
+        );
+        assert_near(
+            &Vec3::new(0.0, 0.0, -1.0),
+            &t.transform_vector(&Vec3::new(1.0, 0.0, 0.0)),
+            1e-5,
+        );
+        let back = t.inverse().transform_point(&Vec3::new(1.0, 0.0, -1.0));
+        assert_near(&Vec3::new(1.0, 0.0, 0.0), &back, 1e-5);
+        let general = Transform::new(*t.matrix()).unwrap();
+        assert_near(
+            &back,
+            &general
+                .inverse()
+                .transform_point(&Vec3::new(1.0, 0.0, -1.0)),
+            1e-5,
+        );
+        assert_near(
+            &Vec3::new(0.0, 1.0, 0.0),
+            &Transform::rotate_z(90.0).transform_vector(&Vec3::new(1.0, 0.0, 0.0)),
+            1e-5,
+        );
+    }
+
+    #[test]
+    fn test_transformed_sphere() {
+        // An ellipsoid with radii 2, 1 and 1 around (0, 0, -5).
+        let t = Transform::translate(Vec3::new(0.0, 0.0, -5.0))
+            * Transform::scale(Vec3::new(2.0, 1.0, 1.0));
+        let ellipsoid = Transformed::new(Sphere::new(Vec3::zero(), 1.0, grey()), t);
+        let r = Ray::new(Vec3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -2.0));
+        let hit = ellipsoid.hit(&r, 0.001, f32::MAX).unwrap();
+        let z = -5.0 + (1.0 - 0.75f32 * 0.75).sqrt();
+        assert!((hit.t() - -z / 2.0).abs() < 1e-5);
+        assert_near(&Vec3::new(1.5, 0.0, z), hit.p(), 1e-5);
+        // The normal of x^2 / 4 + y^2 + z^2 = 1 is along (x / 4, y, z).
+        assert_near(
+            &Vec3::new(1.5 / 4.0, 0.0, z + 5.0).unit_vector(),
+            hit.normal(),
+            1e-5,
+        );
+        let bbox = ellipsoid.bounding_box().unwrap();
+        assert_near(&Vec3::new(-2.0, -1.0, -6.0), bbox.min(), 1e-5);
+        assert_near(&Vec3::new(2.0, 1.0, -4.0), bbox.max(), 1e-5);
+    }
+
+    #[test]
+    fn test_instances() {
+        let mesh = Arc::new(TriangleMesh::new(
+            vec![
+                Vec3::new(-0.5, -0.5, 0.0),
+                Vec3::new(0.5, -0.5, 0.0),
+                Vec3::new(0.0, 0.5, 0.0),
+            ],
+            vec![[0, 1, 2]],
+            grey(),
+        ));
+        let mut list = HitableList::new();
+        for i in 0..1000 {
+            let offset = Vec3::new(
+                (i % 10) as f32,
+                ((i / 10) % 10) as f32,
+                -((i / 100) as f32) - 1.0,
+            );
+            list.push(Box::new(Transformed::new(
+                mesh.clone(),
+                Transform::translate(offset) * Transform::rotate_y(180.0),
+            )));
+        }
+        assert_eq!(1001, Arc::strong_count(&mesh));
+        let bvh = Bvh::new(list);
+        let r = Ray::new(Vec3::new(3.0, 7.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = bvh.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((hit.t() - 2.0).abs() < 1e-5);
+        // Turned around, the triangle now faces away from the ray.
+        assert_near(&Vec3::new(0.0, 0.0, -1.0), hit.normal(), 1e-5);
+        let r = Ray::new(Vec3::new(3.5, 7.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(bvh.hit(&r, 0.001, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn test_animated_transform() {
+        let start = Transform::translate(Vec3::new(0.0, 0.0, -5.0));
+        let end = Transform::translate(Vec3::new(2.0, 0.0, -5.0))
+            * Transform::rotate_y(90.0)
+            * Transform::scale(Vec3::new(3.0, 1.0, 1.0));
+        let motion = AnimatedTransform::new(start, end, 1.0, 2.0);
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        assert_eq!(start, motion.at(0.0));
+        assert_eq!(end, motion.at(2.5));
+        assert_near(
+            &end.transform_point(&x),
+            &motion.at(2.0 - 1e-7).transform_point(&x),
+            1e-5,
+        );
+        // Halfway it has turned by 45 degrees without shrinking.
+        let halfway = Transform::translate(Vec3::new(1.0, 0.0, -5.0))
+            * Transform::rotate_y(45.0)
+            * Transform::scale(Vec3::new(2.0, 1.0, 1.0));
+        assert_near(
+            &halfway.transform_point(&x),
+            &motion.at(1.5).transform_point(&x),
+            1e-5,
+        );
+        assert_near(
+            &halfway.inverse().transform_point(&x),
+            &motion.at(1.5).inverse().transform_point(&x),
+            1e-5,
+        );
+    }
+
+    #[test]
+    fn test_animated_mirror() {
+        let start = Transform::translate(Vec3::new(0.0, 0.0, -5.0));
+        let mirror = Transform::scale(Vec3::new(-1.0, 1.0, 1.0));
+        let end = Transform::translate(Vec3::new(2.0, 0.0, -5.0)) * mirror;
+        let motion = AnimatedTransform::new(start, end, 1.0, 2.0);
+        let p = Vec3::new(1.0, 2.0, 3.0);
+        assert_near(
+            &start.transform_point(&p),
+            &motion.at(1.0 + 1e-6).transform_point(&p),
+            1e-4,
+        );
+        assert_near(
+            &end.transform_point(&p),
+            &motion.at(2.0 - 1e-6).transform_point(&p),
+            1e-4,
+        );
+        // Mirrored at both ends, it stays mirrored on the way.
+        let motion = AnimatedTransform::new(start * mirror, end, 1.0, 2.0);
+        let halfway = Transform::translate(Vec3::new(1.0, 0.0, -5.0)) * mirror;
+        assert_near(
+            &halfway.transform_point(&p),
+            &motion.at(1.5).transform_point(&p),
+            1e-5,
+        );
+    }
+
+    #[test]
+    fn test_animated_hit() {
+        // A unit sphere swinging a quarter turn around the origin at radius 5.
+        let motion = AnimatedTransform::new(
+            Transform::translate(Vec3::new(0.0, 0.0, -5.0)),
+            Transform::rotate_y(90.0) * Transform::translate(Vec3::new(0.0, 0.0, -5.0)),
+            0.0,
+            1.0,
+        );
+        let sphere = Animated::new(Sphere::new(Vec3::zero(), 1.0, grey()), motion);
+        let r = Ray::new(Vec3::zero(), Vec3::new(0.0, 0.0, -1.0));
+        assert!((sphere.hit(&r, 0.001, f32::MAX).unwrap().t() - 4.0).abs() < 1e-5);
+        assert!(sphere.hit(&r.with_time(1.0), 0.001, f32::MAX).is_none());
+        let diagonal = Ray::new(Vec3::zero(), Vec3::new(-1.0, 0.0, -1.0)).with_time(0.5);
+        let hit = sphere.hit(&diagonal, 0.001, f32::MAX).unwrap();
+        assert_near(&Vec3::new(1.0, 0.0, 1.0).unit_vector(), hit.normal(), 1e-5);
+        // Everywhere on the arc lies within the bounds.
+        let bbox = sphere.bounding_box().unwrap();
+        for i in 0..=100 {
+            let hit = sphere
+                .hit(&diagonal.with_time(i as f32 / 100.0), 0.001, f32::MAX)
+                .map(|hit| *hit.p());
+            if let Some(p) = hit {
+                for axis in 0..3 {
+                    assert!(bbox.min()[axis] <= p[axis] && p[axis] <= bbox.max()[axis]);
+                }
+            }
+        }
+        assert!(bbox.max().x() <= 1.1 && bbox.min().x() >= -6.1);
+    }
+}
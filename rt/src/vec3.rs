@@ -47,6 +47,11 @@ impl Vec3 {
         self[2]
     }
 
+    #[inline]
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self[0] + 0.7152 * self[1] + 0.0722 * self[2]
+    }
+
     #[inline]
     pub fn squared_length(&self) -> f32 {
         self.0.iter().map(|v| (*v).powi(2)).sum()
@@ -240,11 +245,11 @@ mod tests {
     fn test_dot_cross() {
         assert_eq!(
             10.0,
-            Vec3::dot(Vec3::new(1.0, 2.0, 3.0), Vec3::new(3.0, 2.0, 1.0))
+            Vec3::dot(&Vec3::new(1.0, 2.0, 3.0), &Vec3::new(3.0, 2.0, 1.0))
         );
         assert_eq!(
             Vec3::new(0.0, 0.0, 1.0),
-            Vec3::cross(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0))
+            Vec3::cross(&Vec3::new(1.0, 0.0, 0.0), &Vec3::new(0.0, 1.0, 0.0))
         );
     }
 }
@@ -1,9 +1,11 @@
-use rt::{ray::Ray, vec3::Vec3};
+use rt::{
+    environment::{Environment, Gradient},
+    ray::Ray,
+    vec3::Vec3,
+};
 
 fn color(r: Ray) -> Vec3 {
-    let unit_direction = r.direction().unit_vector();
-    let t = 0.5 * (unit_direction.y() + 1.0);
-    (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+    Gradient::sky().color(r.direction())
 }
 
 fn main() {
@@ -1,4 +1,8 @@
-use rt::{ray::Ray, vec3::Vec3};
+use rt::{
+    environment::{Environment, Gradient},
+    ray::Ray,
+    vec3::Vec3,
+};
 
 fn hit_sphere(center: &Vec3, radius: f32, r: &Ray) -> bool {
     let oc = *r.origin() - *center;
@@ -12,9 +16,7 @@ fn color(r: &Ray) -> Vec3 {
     if hit_sphere(&Vec3::new(0.0, 0.0, -1.0), 0.5, r) {
         Vec3::new(1.0, 0.0, 0.0)
     } else {
-        let unit_direction = r.direction().unit_vector();
-        let t = 0.5 * (unit_direction.y() + 1.0);
-        (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+        Gradient::sky().color(r.direction())
     }
 }
 
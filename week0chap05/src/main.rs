@@ -1,4 +1,5 @@
 use rt::{
+    environment::{Environment, Gradient},
     hitable::{Hitable, HitableList, Sphere},
     material::Lambertian,
     ray::Ray,
@@ -6,11 +7,10 @@ use rt::{
 };
 
 fn color(r: &Ray, world: &dyn Hitable) -> Vec3 {
-    if let Some(hit) = world.hit(r, 0.0, std::f32::MAX) {
+    if let Some(hit) = world.hit(r, 0.0, f32::MAX) {
         0.5 * (*hit.normal() + Vec3::new(1.0, 1.0, 1.0))
     } else {
-        let t = 0.5 * (r.direction().unit_vector().y() + 1.0);
-        (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+        Gradient::sky().color(r.direction())
     }
 }
 
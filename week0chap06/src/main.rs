@@ -1,23 +1,24 @@
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use rt::{
     camera::Camera,
+    environment::{Environment, Gradient},
     hitable::{Hitable, HitableList, Sphere},
     material::Lambertian,
     ray::Ray,
+    sampler::Sampler,
     vec3::Vec3,
 };
 
 fn color(r: &Ray, world: &dyn Hitable) -> Vec3 {
-    if let Some(hit) = world.hit(r, 0.0, std::f32::MAX) {
+    if let Some(hit) = world.hit(r, 0.0, f32::MAX) {
         0.5 * (*hit.normal() + Vec3::new(1.0, 1.0, 1.0))
     } else {
-        let t = 0.5 * (r.direction().unit_vector().y() + 1.0);
-        (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+        Gradient::sky().color(r.direction())
     }
 }
 
 fn main() {
-    let mut rng = thread_rng();
+    let mut sampler = Sampler::new(0);
     let nx = 200;
     let ny = 100;
     let ns = 100;
@@ -38,9 +39,9 @@ fn main() {
         for i in 0..nx {
             let mut col = Vec3::zero();
             for _ in 0..ns {
-                let u = (i as f32 + rng.gen::<f32>()) / nx as f32;
-                let v = (j as f32 + rng.gen::<f32>()) / ny as f32;
-                let r = camera.get_ray(u, v);
+                let u = (i as f32 + sampler.gen::<f32>()) / nx as f32;
+                let v = (j as f32 + sampler.gen::<f32>()) / ny as f32;
+                let r = camera.get_ray(u, v, &mut sampler);
                 col += color(&r, &world);
             }
             col /= ns as f32;
@@ -1,34 +1,34 @@
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use rt::{
     camera::Camera,
+    environment::{Environment, Gradient},
     hitable::{Hitable, HitableList, Sphere},
     material::Lambertian,
     ray::Ray,
+    sampler::Sampler,
     vec3::Vec3,
 };
 
-fn random_in_unit_sphere() -> Vec3 {
-    let mut rng = thread_rng();
+fn random_in_unit_sphere(sampler: &mut Sampler) -> Vec3 {
     loop {
-        let p = 2.0 * Vec3::new(rng.gen(), rng.gen(), rng.gen()) - Vec3::new(1.0, 1.0, 1.0);
+        let p = 2.0 * Vec3::new(sampler.gen(), sampler.gen(), sampler.gen()) - Vec3::new(1.0, 1.0, 1.0);
         if p.squared_length() < 1.0 {
             break p;
         }
     }
 }
 
-fn color(r: &Ray, world: &dyn Hitable) -> Vec3 {
-    if let Some(hit) = world.hit(r, 0.001, std::f32::MAX) {
-        let target = *hit.p() + *hit.normal() + random_in_unit_sphere();
-        0.5 * color(&Ray::new(*hit.p(), target - *hit.p()), world)
+fn color(r: &Ray, world: &dyn Hitable, sampler: &mut Sampler) -> Vec3 {
+    if let Some(hit) = world.hit(r, 0.001, f32::MAX) {
+        let target = *hit.p() + *hit.normal() + random_in_unit_sphere(sampler);
+        0.5 * color(&Ray::new(*hit.p(), target - *hit.p()), world, sampler)
     } else {
-        let t = 0.5 * (r.direction().unit_vector().y() + 1.0);
-        (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
+        Gradient::sky().color(r.direction())
     }
 }
 
 fn main() {
-    let mut rng = thread_rng();
+    let mut sampler = Sampler::new(0);
     let nx = 200;
     let ny = 100;
     let ns = 100;
@@ -49,10 +49,10 @@ fn main() {
         for i in 0..nx {
             let mut col = Vec3::zero();
             for _ in 0..ns {
-                let u = (i as f32 + rng.gen::<f32>()) / nx as f32;
-                let v = (j as f32 + rng.gen::<f32>()) / ny as f32;
-                let r = camera.get_ray(u, v);
-                col += color(&r, &world);
+                let u = (i as f32 + sampler.gen::<f32>()) / nx as f32;
+                let v = (j as f32 + sampler.gen::<f32>()) / ny as f32;
+                let r = camera.get_ray(u, v, &mut sampler);
+                col += color(&r, &world, &mut sampler);
             }
             col /= ns as f32;
             col = col.sqrt();
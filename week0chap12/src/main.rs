@@ -1,34 +1,22 @@
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use rt::{
+    bvh::Bvh,
     camera::Camera,
-    hitable::{Hitable, HitableList, Sphere},
+    environment::{Environment, Equirectangular, Gradient},
+    hitable::{HitableList, Sphere},
     material::{Dielectric, Lambertian, Metal},
-    ray::Ray,
+    quadric::Plane,
+    render::Renderer,
+    sampler::Sampler,
+    scene::Scene,
     vec3::Vec3,
 };
+use std::env;
 
-fn color(r: &Ray, world: &dyn Hitable, depth: u8) -> Vec3 {
-    if let Some(hit) = world.hit(r, 0.001, std::f32::MAX) {
-        if depth < 50 {
-            if let Some((attenuation, scattered)) = hit.material().scatter(r, &hit) {
-                return attenuation * color(&scattered, world, depth + 1);
-            }
-        }
-        return Vec3::zero();
-    } else {
-        let t = 0.5 * (r.direction().unit_vector().y() + 1.0);
-        (1.0 - t) * Vec3::new(1.0, 1.0, 1.0) + t * Vec3::new(0.5, 0.7, 1.0)
-    }
-}
+const SEED: u64 = 2019;
 
-fn gen_world() -> HitableList {
-    let mut rng = thread_rng();
+fn gen_world(rng: &mut Sampler) -> HitableList {
     let mut world = HitableList::new();
-    world.push(Box::new(Sphere::new(
-        Vec3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
-    )));
     for a in -11..11 {
         for b in -11..11 {
             let choose_nat: f32 = rng.gen();
@@ -66,12 +54,18 @@ fn gen_world() -> HitableList {
 }
 
 fn main() {
-    let mut rng = thread_rng();
     let nx = 300;
     let ny = 200;
     let ns = 100;
-    println!("P3\n{} {}\n255", nx, ny);
-    let world = gen_world();
+    let spheres = Bvh::new(gen_world(&mut Sampler::new(SEED)));
+    // The ground is unbounded, so it stays out of the BVH.
+    let mut world = HitableList::new();
+    world.push(Box::new(Plane::new(
+        Vec3::zero(),
+        Vec3::new(0.0, 1.0, 0.0),
+        Box::new(Lambertian::new(Vec3::new(0.5, 0.5, 0.5))),
+    )));
+    world.push(Box::new(spheres));
     let lookfrom = Vec3::new(15.0, 3.0, 4.0);
     let lookat = Vec3::new(0.0, 0.0, 0.0);
     let aperture = 0.1;
@@ -85,19 +79,12 @@ fn main() {
         aperture,
         dist_to_focus,
     );
-    for j in (0..ny).rev() {
-        for i in 0..nx {
-            let mut col = Vec3::zero();
-            for _ in 0..ns {
-                let u = (i as f32 + rng.gen::<f32>()) / nx as f32;
-                let v = (j as f32 + rng.gen::<f32>()) / ny as f32;
-                let r = camera.get_ray(u, v);
-                col += color(&r, &world, 0);
-            }
-            col /= ns as f32;
-            col = col.sqrt();
-            col *= 255.99;
-            println!("{} {} {}", col[0] as u8, col[1] as u8, col[2] as u8);
-        }
-    }
+    let environment: Box<dyn Environment> = match env::args().nth(2) {
+        Some(path) => Box::new(Equirectangular::load(path).unwrap()),
+        None => Box::new(Gradient::sky()),
+    };
+    let scene = Scene::new(Box::new(world), environment);
+    let image = Renderer::new(nx, ny, ns).with_seed(SEED).render(&scene, &camera);
+    let output = env::args().nth(1).unwrap_or_else(|| "week0chap12.png".to_string());
+    image.save(output).unwrap();
 }